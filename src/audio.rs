@@ -0,0 +1,92 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AudioContext, AudioContextState, AudioWorkletNode, AudioWorkletNodeOptions, MessageEvent};
+
+const WORKLET_URL: &str = "audio-worklet.js";
+const PROCESSOR_NAME: &str = "nes-audio-processor";
+
+// must match BUFFER_SIZE in audio-worklet.js
+const BUFFER_SIZE: u32 = 8192;
+const TARGET_FILL: f64 = 0.5;
+// maximum deviation of the resampling ratio, small enough to be inaudible
+const MAX_RATE_DELTA: f64 = 0.005;
+
+pub struct AudioOutput {
+    context: AudioContext,
+    node: AudioWorkletNode,
+    fill: Rc<Cell<u32>>,
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
+}
+
+pub async fn new_audio_output() -> Result<AudioOutput, JsValue> {
+    let context = AudioContext::new()?;
+    JsFuture::from(context.audio_worklet()?.add_module(WORKLET_URL)?).await?;
+
+    let options = AudioWorkletNodeOptions::new();
+    options.set_output_channel_count(&js_sys::Array::of1(&JsValue::from(1)));
+    let node = AudioWorkletNode::new_with_options(&context, PROCESSOR_NAME, &options)?;
+    node.connect_with_audio_node(&context.destination())?;
+
+    // the worklet periodically reports how many samples it has buffered
+    let fill = Rc::new(Cell::new(0));
+    let reported = fill.clone();
+    let onmessage = Closure::wrap(Box::new(move |event: MessageEvent| {
+        if let Some(count) = event.data().as_f64() {
+            reported.set(count as u32);
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    node.port()?.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+
+    return Ok(AudioOutput {
+        context: context,
+        node: node,
+        fill: fill,
+        _onmessage: onmessage,
+    });
+}
+
+// browsers keep the context suspended until a user gesture
+pub fn resume(audio: &AudioOutput) {
+    if audio.context.state() == AudioContextState::Running {
+        return;
+    }
+    let _ = audio.context.resume();
+    if let Ok(port) = audio.node.port() {
+        if port.post_message(&JsValue::NULL).is_ok() {
+            audio.fill.set(0);
+        }
+    }
+}
+
+pub fn sample_rate(audio: &AudioOutput) -> f64 {
+    return audio.context.sample_rate() as f64;
+}
+
+pub fn fill_level(audio: &AudioOutput) -> f64 {
+    return (audio.fill.get() as f64) / (BUFFER_SIZE as f64);
+}
+
+// dynamic rate control: produce slightly more samples while the buffer is
+// below the target and slightly fewer while it is above
+pub fn rate_ratio(audio: &AudioOutput) -> f64 {
+    let error = (TARGET_FILL - fill_level(audio)) / TARGET_FILL;
+    return 1.0 + error.max(-1.0).min(1.0) * MAX_RATE_DELTA;
+}
+
+pub fn push_samples(audio: &AudioOutput, samples: &[f32]) {
+    if samples.len() == 0 {
+        return;
+    }
+    let array = js_sys::Float32Array::from(samples);
+    let port = match audio.node.port() {
+        Err(_) => return,
+        Ok(port) => port,
+    };
+    if port.post_message_with_transferable(&array, &js_sys::Array::of1(&array.buffer())).is_ok() {
+        // account for what is in flight until the worklet reports again
+        audio.fill.set(audio.fill.get() + samples.len() as u32);
+    }
+}
@@ -0,0 +1,385 @@
+// runs a ROM without a browser, for screenshots, recordings and automated checks
+//
+// usage: headless <rom> [--frames N] [--region ntsc|pal|dendy] [--console famicom|nes] [--no-sprite-limit] [--screenshot PATH] [--record PATH] [--wav PATH] [--sample-rate N] [--input PATH] [--scaler NAME] [--raw]
+//        headless --manifest FILE [--update]
+//
+// --record picks GIF, APNG or Y4M by the extension (.gif, .png/.apng, .y4m),
+// --console nes mutes the expansion audio of the cartridge like a front loader does,
+// --no-sprite-limit draws every sprite of a line instead of the first 8,
+// --scaler upscales the screenshot and the recording with scale2x, scale3x, hq2x or xbr,
+// --raw skips the display pipeline for the screenshot and the recording,
+// --input plays back an input log, see record/input_log.rs.
+// --manifest checks the frame hashes and test rom results listed in FILE, see
+// manifest.rs, and --update stores the current frames as the golden ones instead
+mod manifest;
+
+use rust_webpack_template::nes;
+use rust_webpack_template::video;
+use rust_webpack_template::record;
+use std::path::Path;
+use std::process;
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+struct Options {
+    rom_path: String,
+    frames: u64,
+    region: Option<nes::region::Region>,
+    console_type: nes::apu::ConsoleType,
+    sprite_limit: bool,
+    screenshot: Option<String>,
+    record: Option<(String, record::Format)>,
+    wav: Option<String>,
+    sample_rate: u32,
+    input_log: Option<String>,
+    scaler: video::scale::Scaler,
+    raw: bool,
+    manifest: Option<String>,
+    update: bool,
+}
+
+fn usage() -> ! {
+    eprintln!("usage: headless <rom> [--frames N] [--region ntsc|pal|dendy] [--console famicom|nes] [--no-sprite-limit] [--screenshot PATH] [--record PATH] [--wav PATH] [--sample-rate N] [--input PATH] [--scaler NAME] [--raw]");
+    eprintln!("       headless --manifest FILE [--update]");
+    process::exit(2);
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        rom_path: String::new(),
+        frames: 60,
+        region: None,
+        console_type: nes::apu::ConsoleType::Famicom,
+        sprite_limit: true,
+        screenshot: None,
+        record: None,
+        wav: None,
+        sample_rate: DEFAULT_SAMPLE_RATE,
+        input_log: None,
+        scaler: video::scale::Scaler::None,
+        raw: false,
+        manifest: None,
+        update: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                options.frames = args.next().and_then(|v| v.parse().ok()).unwrap_or_else(|| usage());
+            }
+            "--region" => {
+                options.region = match args.next().as_ref().map(|v| v.as_str()) {
+                    Some("ntsc") => Some(nes::region::Region::Ntsc),
+                    Some("pal") => Some(nes::region::Region::Pal),
+                    Some("dendy") => Some(nes::region::Region::Dendy),
+                    _ => usage(),
+                };
+            }
+            "--console" => {
+                options.console_type = match args.next().as_ref().map(|v| v.as_str()) {
+                    Some("famicom") => nes::apu::ConsoleType::Famicom,
+                    Some("nes") => nes::apu::ConsoleType::Nes,
+                    _ => usage(),
+                };
+            }
+            "--no-sprite-limit" => {
+                options.sprite_limit = false;
+            }
+            "--screenshot" => {
+                options.screenshot = Some(args.next().unwrap_or_else(|| usage()));
+            }
+            "--record" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let format = record::format_from_path(&path).unwrap_or_else(|| usage());
+                options.record = Some((path, format));
+            }
+            "--wav" => {
+                options.wav = Some(args.next().unwrap_or_else(|| usage()));
+            }
+            "--sample-rate" => {
+                options.sample_rate = args.next().and_then(|v| v.parse().ok()).unwrap_or_else(|| usage());
+            }
+            "--input" => {
+                options.input_log = Some(args.next().unwrap_or_else(|| usage()));
+            }
+            "--scaler" => {
+                options.scaler = args.next().and_then(|v| video::scale::from_name(&v)).unwrap_or_else(|| usage());
+            }
+            "--raw" => {
+                options.raw = true;
+            }
+            "--manifest" => {
+                options.manifest = Some(args.next().unwrap_or_else(|| usage()));
+            }
+            "--update" => {
+                options.update = true;
+            }
+            _ => {
+                if arg.starts_with("--") || !options.rom_path.is_empty() {
+                    usage();
+                }
+                options.rom_path = arg;
+            }
+        }
+    }
+    if options.rom_path.is_empty() == options.manifest.is_none() {
+        usage();
+    }
+    return options;
+}
+
+fn fail(what: &str, path: &str, why: std::io::Error) -> ! {
+    eprintln!("couldn't {} {}: {}", what, path, why);
+    process::exit(1);
+}
+
+struct Console {
+    cpu: nes::cpu::Cpu,
+    ppu: nes::ppu::Ppu,
+    mem: nes::memory::Memory,
+    apu: nes::apu::Apu,
+    region: nes::region::Region,
+    frame_buffer: Vec<u16>,
+    input_log: Vec<record::input_log::Frame>,
+    frame_number: usize,
+}
+
+// the region of the header unless one is given
+fn power_on(rom_path: &str, region: Option<nes::region::Region>, sample_rate: u32) -> Result<Console, String> {
+    let romdata = std::fs::read(rom_path).map_err(|why| why.to_string())?;
+    let nes_rom = nes::rom::load_nes_data(&romdata).map_err(|why| why.to_string())?;
+    let region = region.unwrap_or(nes::rom::get_region(&nes_rom.header));
+
+    let mut console = Console {
+        cpu: nes::cpu::new_cpu(),
+        ppu: nes::ppu::new_ppu(&nes_rom.character_rom.data),
+        mem: nes::memory::new_memory(&nes_rom.program_rom.data),
+        apu: nes::apu::new_apu(),
+        region: region,
+        frame_buffer: vec![0; 256*240],
+        input_log: Vec::new(),
+        frame_number: 0,
+    };
+    if let Some(source) = nes::apu::new_expansion_audio(nes::rom::get_mapper(&nes_rom.header)) {
+        nes::memory::set_expansion_audio(&mut console.mem, source);
+    }
+    nes::ppu::set_mirroring(&mut console.ppu, nes::rom::get_mirroring(&nes_rom.header));
+    nes::ppu::set_region(&mut console.ppu, region);
+    nes::apu::set_region(&mut console.apu, region);
+    nes::apu::set_sample_rate(&mut console.apu, sample_rate as f64);
+
+    let mut vmem = nes::vmem::new_vmem(&mut console.mem, &mut console.ppu, &mut console.apu);
+    nes::cpu::reset(&mut console.cpu, &mut vmem);
+    return Ok(console);
+}
+
+fn load_input_log(console: &mut Console, path: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|why| why.to_string())?;
+    console.input_log = record::input_log::parse(&text)?;
+    return Ok(());
+}
+
+// runs until the next frame is drawn and returns its audio samples
+fn run_frame(console: &mut Console) -> Vec<f32> {
+    let released = record::input_log::Frame { buttons: [0; nes::input::PORTS], turbo: [0; nes::input::PORTS], turbo_rate: None };
+    let frame = console.input_log.get(console.frame_number).cloned().unwrap_or(released);
+    console.frame_number += 1;
+    if let Some((period, on_frames)) = frame.turbo_rate {
+        nes::input::set_turbo_rate(&mut console.mem.input, period, on_frames);
+    }
+    for port in 0..nes::input::PORTS {
+        nes::input::set_buttons(&mut console.mem.input, port, frame.buttons[port]);
+        nes::input::set_turbo_buttons(&mut console.mem.input, port, frame.turbo[port]);
+    }
+    let mut vmem = nes::vmem::new_vmem(&mut console.mem, &mut console.ppu, &mut console.apu);
+    nes::system::run_frame(&mut console.cpu, &mut vmem, &mut console.frame_buffer);
+    return nes::apu::take_samples(&mut vmem.apu);
+}
+
+fn run(options: &Options) {
+    let mut console = match power_on(&options.rom_path, options.region, options.sample_rate) {
+        Ok(console) => console,
+        Err(why) => {
+            eprintln!("couldn't load {}: {}", options.rom_path, why);
+            process::exit(1);
+        }
+    };
+    let mut mixer = nes::apu::new_mixer();
+    nes::apu::set_console_type(&mut mixer, options.console_type);
+    nes::apu::set_mixer(&mut console.apu, &mixer);
+    nes::ppu::set_sprite_limit(&mut console.ppu, options.sprite_limit);
+    if let Some(path) = options.input_log.as_ref() {
+        if let Err(why) = load_input_log(&mut console, path) {
+            eprintln!("couldn't read {}: {}", path, why);
+            process::exit(1);
+        }
+    }
+    let rom_name = Path::new(&options.rom_path).file_name().unwrap().to_string_lossy().to_string();
+    let mut display = video::display::new_display();
+    video::display::set_region(&mut display, console.region);
+    video::display::set_scaler(&mut display, options.scaler);
+
+    let mut recorder = options.record.as_ref().map(|(path, format)| {
+        let rate = nes::region::frame_rate_ratio(console.region);
+        return record::new_recorder(path, *format, rate).unwrap_or_else(|why| fail("create", path, why));
+    });
+    let mut wav = options.wav.as_ref().map(|path| {
+        return record::new_wav_recorder(path, options.sample_rate).unwrap_or_else(|why| fail("create", path, why));
+    });
+
+    for _ in 0..options.frames {
+        // the samples must not pile up even when they are not recorded
+        let samples = run_frame(&mut console);
+        if let (Some(wav), Some(path)) = (wav.as_mut(), options.wav.as_ref()) {
+            record::add_samples(wav, &samples).unwrap_or_else(|why| fail("write", path, why));
+        }
+        if let (Some(recorder), Some((path, _))) = (recorder.as_mut(), options.record.as_ref()) {
+            let (rgba, width, height) = if options.raw {
+                (video::display::render_raw(&display, &console.frame_buffer), 256, 240)
+            } else {
+                video::display::render(&mut display, &console.frame_buffer, nes::ppu::colour_phase(&console.ppu))
+            };
+            record::add_frame(recorder, &rgba, width, height).unwrap_or_else(|why| fail("write", path, why));
+        }
+    }
+    if let (Some(recorder), Some((path, _))) = (recorder, options.record.as_ref()) {
+        record::finish(recorder).unwrap_or_else(|why| fail("write", path, why));
+    }
+    if let (Some(wav), Some(path)) = (wav, options.wav.as_ref()) {
+        record::finish_wav(wav).unwrap_or_else(|why| fail("write", path, why));
+    }
+    println!("{} frames, hash {:08x}", options.frames, video::display::frame_hash(&console.frame_buffer));
+
+    if let Some(path) = options.screenshot.as_ref() {
+        let colour_phase = nes::ppu::colour_phase(&console.ppu);
+        let png = video::display::screenshot(&mut display, &console.frame_buffer, colour_phase, options.raw, &rom_name, options.frames);
+        if let Err(why) = std::fs::write(path, png) {
+            fail("write", path, why);
+        }
+    }
+}
+
+// runs every entry of the manifest from power on, returns whether all of them matched
+fn check_manifest(manifest_path: &str, update: bool) -> bool {
+    let text = std::fs::read_to_string(manifest_path).unwrap_or_else(|why| fail("read", manifest_path, why));
+    let base = Path::new(manifest_path).parent().unwrap_or(Path::new("."));
+    let entries = match manifest::parse(&text, base) {
+        Ok(entries) => entries,
+        Err(why) => {
+            eprintln!("{}: {}", manifest_path, why);
+            process::exit(2);
+        }
+    };
+
+    let mut passed = true;
+    let mut hashes = Vec::new();
+    for entry in &entries {
+        let rom_path = entry.rom.to_string_lossy().to_string();
+        let name = format!("{}:{} {} after {} frames", manifest_path, entry.line, rom_path, entry.frames);
+        let mut console = match power_on(&rom_path, None, DEFAULT_SAMPLE_RATE) {
+            Ok(console) => console,
+            Err(why) => {
+                println!("FAIL {}: couldn't load {}: {}", name, rom_path, why);
+                passed = false;
+                continue;
+            }
+        };
+        if let Some(input_log) = entry.input_log.as_ref() {
+            if let Err(why) = load_input_log(&mut console, &input_log.to_string_lossy()) {
+                println!("FAIL {}: couldn't read {}: {}", name, input_log.to_string_lossy(), why);
+                passed = false;
+                continue;
+            }
+        }
+        if entry.check == manifest::Check::Pass {
+            if update {
+                continue;
+            }
+            let mut result = None;
+            for _ in 0..entry.frames {
+                run_frame(&mut console);
+                result = manifest::blargg_result(&console.mem.backup_ram);
+                if result.is_some() {
+                    break;
+                }
+            }
+            match result {
+                Some(Ok(())) => println!("ok {}", name),
+                Some(Err(why)) => {
+                    println!("FAIL {}: {}", name, why);
+                    passed = false;
+                }
+                None => {
+                    println!("FAIL {}: no result yet", name);
+                    passed = false;
+                }
+            }
+            continue;
+        }
+        let expected_hash = match entry.check {
+            manifest::Check::Hash(hash) => hash,
+            manifest::Check::Pass => None,
+        };
+
+        for _ in 0..entry.frames {
+            run_frame(&mut console);
+        }
+        let hash = video::display::frame_hash(&console.frame_buffer);
+        let golden_path = manifest::golden_path(base, hash);
+
+        if update {
+            let golden = golden_path.to_string_lossy().to_string();
+            if let Some(dir) = golden_path.parent() {
+                std::fs::create_dir_all(dir).unwrap_or_else(|why| fail("create", &dir.to_string_lossy(), why));
+            }
+            std::fs::write(&golden_path, video::display::frame_to_bytes(&console.frame_buffer)).unwrap_or_else(|why| fail("write", &golden, why));
+            if expected_hash != Some(hash) {
+                println!("updated {}: {:08x}", name, hash);
+            }
+            hashes.push((entry.line, hash));
+            continue;
+        }
+
+        if expected_hash == Some(hash) {
+            println!("ok {}", name);
+            continue;
+        }
+        passed = false;
+        match expected_hash {
+            Some(expected) => println!("FAIL {}: expected {:08x}, got {:08x}", name, expected, hash),
+            None => println!("FAIL {}: no hash yet, got {:08x}", name, hash),
+        }
+
+        // the golden frame of the expected hash shows what changed
+        let expected_frame = expected_hash
+            .and_then(|expected| std::fs::read(manifest::golden_path(base, expected)).ok())
+            .and_then(|bytes| video::display::frame_from_bytes(&bytes));
+        if let Some(expected_frame) = expected_frame {
+            let mut display = video::display::new_display();
+            video::display::set_region(&mut display, console.region);
+            let (rgba, width, height) = video::display::diff_image(&display, &expected_frame, &console.frame_buffer);
+            let stem = entry.rom.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let diff_path = base.join(format!("{}-{}-line{}-diff.png", stem, entry.frames, entry.line)).to_string_lossy().to_string();
+            let png = video::png::encode_png(&rgba, width, height, &[("Hash", format!("{:08x}", hash))]);
+            std::fs::write(&diff_path, png).unwrap_or_else(|why| fail("write", &diff_path, why));
+            println!("     wrote {}", diff_path);
+        }
+    }
+
+    if update {
+        std::fs::write(manifest_path, manifest::update(&text, &hashes)).unwrap_or_else(|why| fail("write", manifest_path, why));
+    }
+    return passed;
+}
+
+fn main() {
+    let options = parse_options();
+    match options.manifest.as_ref() {
+        Some(path) => {
+            if !check_manifest(path, options.update) {
+                process::exit(1);
+            }
+        }
+        None => run(&options),
+    }
+}
@@ -0,0 +1,169 @@
+// golden frame manifests, each line asserts the hash of the framebuffer
+// after a number of frames from power on:
+//
+//     # rom              frames  hash      input log
+//     roms/nestest.nes   60      1a2b3c4d
+//     roms/game.nes      600     -         logs/game-start.txt
+//     roms/apu_test.nes  1800    pass
+//
+// a hash of - is not known yet and fails until --update fills it in. pass
+// leaves the check to a test rom that reports its result at $6000 the way
+// blargg's do, within the given number of frames. paths
+// are relative to the manifest, the golden frames are kept next to it as
+// golden/<hash>.frame, the 256x240 colour indices as little endian u16 whose
+// crc32 is the hash. a mismatch writes <rom>-<frames>-line<n>-diff.png next to
+// the manifest
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Check {
+    Hash(Option<u32>),
+    Pass,
+}
+
+pub struct Entry {
+    // 1 based, for messages and updates
+    pub line: usize,
+    pub rom: PathBuf,
+    pub frames: u64,
+    pub check: Check,
+    pub input_log: Option<PathBuf>,
+}
+
+pub fn parse(text: &str, base: &Path) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        if fields.len() < 3 || fields.len() > 4 {
+            return Err(format!("line {}: expected rom, frames, hash and an optional input log", index + 1));
+        }
+        let frames = fields[1].parse().map_err(|_| format!("line {}: bad frame count {}", index + 1, fields[1]))?;
+        let check = match fields[2] {
+            "-" => Check::Hash(None),
+            "pass" => Check::Pass,
+            hash => Check::Hash(Some(u32::from_str_radix(hash, 16).map_err(|_| format!("line {}: bad hash {}", index + 1, hash))?)),
+        };
+        entries.push(Entry {
+            line: index + 1,
+            rom: base.join(fields[0]),
+            frames: frames,
+            check: check,
+            input_log: fields.get(3).map(|path| base.join(path)),
+        });
+    }
+    return Ok(entries);
+}
+
+pub fn golden_path(base: &Path, hash: u32) -> PathBuf {
+    return base.join("golden").join(format!("{:08x}.frame", hash));
+}
+
+// what a blargg test rom reports in the ram at $6000: the status byte, the
+// signature de b0 61 once it is valid, and the text of the result from $6004.
+// None while it is still running or hasn't written the signature yet
+pub fn blargg_result(ram: &[u8]) -> Option<Result<(), String>> {
+    if ram.len() < 4 || ram[1..4] != [0xDE, 0xB0, 0x61] {
+        return None;
+    }
+    let text: Vec<u8> = ram[4..].iter().cloned().take_while(|&byte| byte != 0).collect();
+    let text = String::from_utf8_lossy(&text).trim().to_string();
+    return match ram[0] {
+        0x00 => Some(Ok(())),
+        0x80 => None,
+        0x81 => Some(Err("asks for a reset".to_string())),
+        code => Some(Err(format!("result {}: {}", code, text))),
+    };
+}
+
+// byte range of the third field, the hash
+fn hash_range(line: &str) -> Option<(usize, usize)> {
+    let mut start = None;
+    let mut field = 0;
+    for (position, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(position),
+            (true, Some(begin)) => {
+                field += 1;
+                if field == 3 {
+                    return Some((begin, position));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    return None;
+}
+
+// replaces the hash of the given lines, keeping everything else as it was
+pub fn update(text: &str, hashes: &[(usize, u32)]) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (index, line) in text.lines().enumerate() {
+        let hash = hashes.iter().find(|(number, _)| *number == index + 1);
+        match (hash, hash_range(line)) {
+            (Some((_, hash)), Some((start, end))) => {
+                out.push_str(&line[..start]);
+                out.push_str(&format!("{:08x}", hash));
+                out.push_str(&line[end..]);
+            }
+            _ => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "# rom  frames  hash  input log\n\nroms/a.nes  60  1a2b3c4d\nroms/b.nes  600  -  logs/b.txt  # not checked yet\nroms/c.nes  900  pass\n";
+
+    #[test]
+    fn entries_are_read_relative_to_the_manifest() {
+        let entries = parse(MANIFEST, Path::new("tests/frames")).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[0].line, entries[0].frames, entries[0].check), (3, 60, Check::Hash(Some(0x1a2b3c4d))));
+        assert_eq!(entries[0].rom, Path::new("tests/frames/roms/a.nes"));
+        assert!(entries[0].input_log.is_none());
+        assert_eq!((entries[1].line, entries[1].check), (4, Check::Hash(None)));
+        assert_eq!(entries[1].input_log, Some(PathBuf::from("tests/frames/logs/b.txt")));
+        assert_eq!((entries[2].frames, entries[2].check), (900, Check::Pass));
+    }
+
+    #[test]
+    fn bad_lines_are_reported_by_number() {
+        let error = |text: &str| parse(text, Path::new(".")).err().unwrap();
+        assert_eq!(error("a.nes 60\n"), "line 1: expected rom, frames, hash and an optional input log");
+        assert_eq!(error("# x\na.nes sixty -\n"), "line 2: bad frame count sixty");
+        assert_eq!(error("a.nes 60 xyz\n"), "line 1: bad hash xyz");
+    }
+
+    fn blargg_ram(status: u8, text: &str) -> Vec<u8> {
+        let mut ram = vec![status, 0xDE, 0xB0, 0x61];
+        ram.extend_from_slice(text.as_bytes());
+        ram.push(0);
+        return ram;
+    }
+
+    #[test]
+    fn blargg_results_need_the_signature() {
+        assert_eq!(blargg_result(&[0x00, 0x00, 0x00, 0x00]), None);
+        assert_eq!(blargg_result(&blargg_ram(0x80, "")), None);
+        assert_eq!(blargg_result(&blargg_ram(0x00, "\nPassed\n")), Some(Ok(())));
+        assert_eq!(blargg_result(&blargg_ram(0x81, "")), Some(Err("asks for a reset".to_string())));
+        assert_eq!(blargg_result(&blargg_ram(0x02, "\nVBL period is too short\n\nFailed #2\n")), Some(Err("result 2: VBL period is too short\n\nFailed #2".to_string())));
+    }
+
+    #[test]
+    fn update_only_replaces_the_hashes() {
+        let updated = update(MANIFEST, &[(4, 0xcafe), (3, 0x12345678)]);
+        assert_eq!(updated, "# rom  frames  hash  input log\n\nroms/a.nes  60  12345678\nroms/b.nes  600  0000cafe  logs/b.txt  # not checked yet\nroms/c.nes  900  pass\n");
+        let entries = parse(&updated, Path::new(".")).unwrap();
+        assert_eq!(entries[1].check, Check::Hash(Some(0xcafe)));
+        assert_eq!(golden_path(Path::new("m"), 0xcafe), Path::new("m/golden/0000cafe.frame"));
+    }
+}
@@ -0,0 +1,276 @@
+// maps keys and gamepad inputs of the browser to the buttons of each player's
+// controller, the table is edited by the page and saved as JSON
+use super::nes::input;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+// the controller buttons in the order of their bits followed by the turbo
+// buttons, the bindings of a player follow it
+pub const BUTTON_NAMES: [&str; 10] = ["A", "B", "Select", "Start", "Up", "Down", "Left", "Right", "TurboA", "TurboB"];
+// the controller bit of each turbo button
+const TURBO_BUTTONS: [u8; 2] = [input::BUTTON_A, input::BUTTON_B];
+
+// how far a stick has to be pushed to press a button
+const AXIS_THRESHOLD: f64 = 0.5;
+
+// written as key:<KeyboardEvent.code>, button:<n> or axis:<n>+ / axis:<n>- in the
+// JSON and by the page, n follows the standard gamepad mapping
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Source {
+    Key(String),
+    Button(u32),
+    // true for the positive direction
+    Axis(u32, bool),
+}
+
+impl From<Source> for String {
+    fn from(source: Source) -> String {
+        match source {
+            Source::Key(code) => return format!("key:{}", code),
+            Source::Button(index) => return format!("button:{}", index),
+            Source::Axis(index, positive) => return format!("axis:{}{}", index, if positive { "+" } else { "-" }),
+        }
+    }
+}
+
+impl TryFrom<String> for Source {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Source, String> {
+        let error = || format!("unknown input {}", text);
+        let mut parts = text.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let value = parts.next().ok_or_else(error)?;
+        match kind {
+            "key" => return Ok(Source::Key(value.to_string())),
+            "button" => return Ok(Source::Button(value.parse().map_err(|_| error())?)),
+            "axis" => {
+                let positive = match value.chars().last() {
+                    Some('+') => true,
+                    Some('-') => false,
+                    _ => return Err(error()),
+                };
+                let index = value[..value.len() - 1].parse().map_err(|_| error())?;
+                return Ok(Source::Axis(index, positive));
+            }
+            _ => return Err(error()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Player {
+    // index of the gamepad the player uses, none plays on the keyboard only
+    pub gamepad: Option<u32>,
+    // the inputs that press each button, in the order of BUTTON_NAMES
+    pub buttons: Vec<Vec<Source>>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Bindings {
+    pub players: Vec<Player>,
+    // turbo buttons repeat every period frames and are pressed for on_frames of them
+    #[serde(default = "default_turbo_period")]
+    pub turbo_period: u8,
+    #[serde(default = "default_turbo_on_frames")]
+    pub turbo_on_frames: u8,
+}
+
+fn default_turbo_period() -> u8 {
+    return input::DEFAULT_TURBO_PERIOD;
+}
+
+fn default_turbo_on_frames() -> u8 {
+    return input::DEFAULT_TURBO_ON_FRAMES;
+}
+
+// the state of a gamepad as read this frame
+pub struct GamepadState {
+    pub buttons: Vec<bool>,
+    pub axes: Vec<f64>,
+}
+
+fn keys(codes: &[&str]) -> Vec<Vec<Source>> {
+    return codes.iter().map(|code| vec![Source::Key(code.to_string())]).collect();
+}
+
+// the standard gamepad mapping, A on the right face button and B on the
+// bottom one, their turbo buttons on the top and left ones
+fn gamepad_buttons() -> Vec<Vec<Source>> {
+    return vec![
+        vec![Source::Button(1)],
+        vec![Source::Button(0)],
+        vec![Source::Button(8)],
+        vec![Source::Button(9)],
+        vec![Source::Button(12), Source::Axis(1, false)],
+        vec![Source::Button(13), Source::Axis(1, true)],
+        vec![Source::Button(14), Source::Axis(0, false)],
+        vec![Source::Button(15), Source::Axis(0, true)],
+        vec![Source::Button(3)],
+        vec![Source::Button(2)],
+    ];
+}
+
+// player 1 on the arrow keys and the first gamepad, player 2 on WASD and the second
+pub fn default_bindings() -> Bindings {
+    let mut player1 = Player { gamepad: Some(0), buttons: keys(&["KeyX", "KeyZ", "ShiftRight", "Enter", "ArrowUp", "ArrowDown", "ArrowLeft", "ArrowRight", "KeyV", "KeyC"]) };
+    let mut player2 = Player { gamepad: Some(1), buttons: keys(&["KeyG", "KeyF", "KeyR", "KeyT", "KeyW", "KeyS", "KeyA", "KeyD", "KeyJ", "KeyH"]) };
+    for player in [&mut player1, &mut player2].iter_mut() {
+        for (button, sources) in player.buttons.iter_mut().zip(gamepad_buttons()) {
+            button.extend(sources);
+        }
+    }
+    return Bindings {
+        players: vec![player1, player2],
+        turbo_period: input::DEFAULT_TURBO_PERIOD,
+        turbo_on_frames: input::DEFAULT_TURBO_ON_FRAMES,
+    };
+}
+
+// a saved table needs every player with all the buttons, anything else is
+// not a table of ours and the defaults are kept
+pub fn from_saved(bindings: Bindings) -> Option<Bindings> {
+    if bindings.players.len() != input::PORTS || bindings.players.iter().any(|player| player.buttons.len() != BUTTON_NAMES.len()) {
+        return None;
+    }
+    return Some(bindings);
+}
+
+pub fn button_index(name: &str) -> Option<usize> {
+    return BUTTON_NAMES.iter().position(|button| button.eq_ignore_ascii_case(name));
+}
+
+// adds a source to a button, taking it away from any other button of the player
+pub fn bind(bindings: &mut Bindings, player: usize, button: usize, source: Source) {
+    let sources = &mut bindings.players[player].buttons;
+    for other in sources.iter_mut() {
+        other.retain(|bound| *bound != source);
+    }
+    sources[button].push(source);
+}
+
+pub fn clear(bindings: &mut Bindings, player: usize, button: usize) {
+    bindings.players[player].buttons[button].clear();
+}
+
+pub fn set_gamepad(bindings: &mut Bindings, player: usize, gamepad: Option<u32>) {
+    bindings.players[player].gamepad = gamepad;
+}
+
+pub fn set_turbo_rate(bindings: &mut Bindings, period: u8, on_frames: u8) {
+    bindings.turbo_period = period;
+    bindings.turbo_on_frames = on_frames;
+}
+
+pub fn is_active(source: &Source, keys: &HashSet<String>, gamepad: Option<&GamepadState>) -> bool {
+    match (source, gamepad) {
+        (Source::Key(code), _) => return keys.contains(code),
+        (Source::Button(index), Some(gamepad)) => return gamepad.buttons.get(*index as usize).cloned().unwrap_or(false),
+        (Source::Axis(index, positive), Some(gamepad)) => {
+            let value = gamepad.axes.get(*index as usize).cloned().unwrap_or(0.0);
+            return if *positive { value > AXIS_THRESHOLD } else { value < -AXIS_THRESHOLD };
+        }
+        _ => return false,
+    }
+}
+
+// the buttons held and sticks pushed on a gamepad, for binding whatever is pressed next
+pub fn active_sources(gamepad: &GamepadState) -> Vec<Source> {
+    let mut sources = Vec::new();
+    for (index, pressed) in gamepad.buttons.iter().enumerate() {
+        if *pressed {
+            sources.push(Source::Button(index as u32));
+        }
+    }
+    for (index, value) in gamepad.axes.iter().enumerate() {
+        if value.abs() > AXIS_THRESHOLD {
+            sources.push(Source::Axis(index as u32, *value > 0.0));
+        }
+    }
+    return sources;
+}
+
+// the controller bits of the buttons and of the turbo buttons a player holds,
+// gamepad is the one assigned to them if it is connected
+pub fn buttons(bindings: &Bindings, player: usize, keys: &HashSet<String>, gamepad: Option<&GamepadState>) -> (u8, u8) {
+    let mut bits = 0;
+    let mut turbo = 0;
+    for (button, sources) in bindings.players[player].buttons.iter().enumerate() {
+        if !sources.iter().any(|source| is_active(source, keys, gamepad)) {
+            continue;
+        }
+        if button < 8 {
+            bits = bits | (1 << button);
+        } else {
+            turbo = turbo | TURBO_BUTTONS[button - 8];
+        }
+    }
+    return (bits, turbo);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(buttons: usize) -> Player {
+        return Player { gamepad: None, buttons: vec![vec![Source::Button(0)]; buttons] };
+    }
+
+    fn saved(buttons: &[usize]) -> Bindings {
+        let mut bindings = default_bindings();
+        bindings.players = buttons.iter().map(|count| player(*count)).collect();
+        return bindings;
+    }
+
+    #[test]
+    fn sources_round_trip_through_their_text() {
+        let sources = vec![Source::Key("ShiftRight".to_string()), Source::Button(12), Source::Axis(1, false), Source::Axis(0, true)];
+        for source in sources {
+            let text = String::from(source.clone());
+            assert_eq!(Source::try_from(text), Ok(source));
+        }
+        assert_eq!(String::from(Source::Axis(3, false)), "axis:3-");
+        for text in &["key", "button:x", "axis:2", "axis:+", "pedal:1"] {
+            assert_eq!(Source::try_from(text.to_string()), Err(format!("unknown input {}", text)));
+        }
+    }
+
+    #[test]
+    fn binding_a_source_moves_it_between_buttons() {
+        let mut bindings = default_bindings();
+        let up = button_index("up").unwrap();
+        bind(&mut bindings, 1, up, Source::Key("KeyX".to_string()));
+        assert!(bindings.players[1].buttons[up].contains(&Source::Key("KeyX".to_string())));
+        bind(&mut bindings, 1, button_index("A").unwrap(), Source::Key("KeyX".to_string()));
+        assert!(!bindings.players[1].buttons[up].contains(&Source::Key("KeyX".to_string())));
+        // other players keep their own bindings
+        assert!(bindings.players[0].buttons[0].contains(&Source::Key("KeyX".to_string())));
+    }
+
+    #[test]
+    fn sticks_press_past_the_threshold() {
+        let gamepad = GamepadState { buttons: vec![false, true], axes: vec![0.4, -0.9] };
+        assert_eq!(active_sources(&gamepad), vec![Source::Button(1), Source::Axis(1, false)]);
+        assert!(!is_active(&Source::Axis(0, true), &HashSet::new(), Some(&gamepad)));
+        assert!(!is_active(&Source::Button(1), &HashSet::new(), None));
+    }
+
+    #[test]
+    fn saved_tables_need_every_button() {
+        assert!(from_saved(saved(&[10, 10])).is_some());
+        assert!(from_saved(saved(&[8, 10])).is_none());
+        assert!(from_saved(saved(&[11, 10])).is_none());
+        assert!(from_saved(saved(&[3, 10])).is_none());
+        assert!(from_saved(saved(&[10])).is_none());
+    }
+
+    #[test]
+    fn turbo_buttons_press_their_controller_bit() {
+        let mut bindings = default_bindings();
+        bind(&mut bindings, 0, button_index("turboa").unwrap(), Source::Key("KeyQ".to_string()));
+        let keys: HashSet<String> = ["KeyQ", "KeyZ"].iter().map(|code| code.to_string()).collect();
+        assert_eq!(buttons(&bindings, 0, &keys, None), (input::BUTTON_B, input::BUTTON_A));
+    }
+}
@@ -1,11 +1,17 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::rc::Rc;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{Request, RequestInit, RequestMode, Response, console};
-mod nes;
+use web_sys::{Request, RequestInit, RequestMode, Response};
+pub mod nes;
+mod audio;
+mod bindings;
+pub mod video;
+pub mod record;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Branch {
@@ -31,11 +37,13 @@ pub struct Signature {
     pub email: String,
 }
 
+const ROM_URL: &str = "nestest.nes";
+
 async fn load_rom() ->Result<Vec<u8>, JsValue> {
-    let mut opts = RequestInit::new();
-    opts.method("GET");
+    let opts = RequestInit::new();
+    opts.set_method("GET");
 
-    let url = "nestest.nes";
+    let url = ROM_URL;
 
     let request = Request::new_with_str_and_init(&url, &opts)?;
 
@@ -59,19 +67,422 @@ async fn load_rom() ->Result<Vec<u8>, JsValue> {
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+thread_local! {
+    static SPRITE_LIMIT: Cell<bool> = Cell::new(true);
+    static MIXER: RefCell<nes::apu::Mixer> = RefCell::new(nes::apu::new_mixer());
+    static SCOPE: RefCell<Scope> = RefCell::new(Scope {
+        names: Vec::new(),
+        waveforms: Vec::new(),
+    });
+    static DISPLAY: RefCell<video::display::Display> = RefCell::new(video::display::new_display());
+    static LAST_FRAME: RefCell<LastFrame> = RefCell::new(LastFrame {
+        indices: vec![0; 256*240],
+        number: 0,
+        colour_phase: 0,
+    });
+    static BINDINGS: RefCell<bindings::Bindings> = RefCell::new(bindings::default_bindings());
+    // KeyboardEvent.code of the keys held down
+    static HELD_KEYS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    static ACTIVE_GAMEPAD_INPUTS: RefCell<Vec<bindings::Source>> = RefCell::new(Vec::new());
+    static PRESSED_INPUT: RefCell<Option<bindings::Source>> = RefCell::new(None);
+    // the controllers of every frame since power on, saved as an input log,
+    // up to MAX_INPUT_LOG_FRAMES
+    static INPUT_LOG: RefCell<Vec<record::input_log::Frame>> = RefCell::new(Vec::new());
+}
+
+// the last completed frame, kept for screenshots
+struct LastFrame {
+    indices: Vec<u16>,
+    number: u64,
+    colour_phase: u32,
+}
+
+// the audio channels of the last completed frame, kept for the oscilloscope
+struct Scope {
+    names: Vec<String>,
+    waveforms: Vec<Vec<f32>>,
+}
+
+fn update_scope(apu: &nes::apu::Apu) {
+    SCOPE.with(|scope| {
+        let mut scope = scope.borrow_mut();
+        let count = nes::apu::channel_count(apu);
+        scope.names = (0..count).map(|channel| nes::apu::channel_name(apu, channel)).collect();
+        scope.waveforms = (0..count).map(|channel| nes::apu::channel_waveform(apu, channel)).collect();
+    });
+}
+
+const BINDINGS_STORAGE_KEY: &str = "nes-input-bindings";
+
+fn local_storage() -> Option<web_sys::Storage> {
+    return window().local_storage().ok().flatten();
+}
+
+// replaces the default bindings with the saved ones, if there are any
+fn load_bindings() {
+    let saved = local_storage()
+        .and_then(|storage| storage.get_item(BINDINGS_STORAGE_KEY).ok().flatten())
+        .and_then(|text| js_sys::JSON::parse(&text).ok())
+        .and_then(|value| serde_wasm_bindgen::from_value::<bindings::Bindings>(value).ok())
+        .and_then(bindings::from_saved);
+    if let Some(saved) = saved {
+        BINDINGS.with(|bindings| *bindings.borrow_mut() = saved);
+    }
+}
+
+// a plain JSON value, none becomes null
+fn to_json_value<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    return value.serialize(&serde_wasm_bindgen::Serializer::json_compatible()).map_err(JsValue::from);
+}
+
+fn save_bindings() {
+    let value = BINDINGS.with(|bindings| to_json_value(&*bindings.borrow()));
+    let text = value.ok().and_then(|value| js_sys::JSON::stringify(&value).ok());
+    if let (Some(storage), Some(text)) = (local_storage(), text) {
+        let _ = storage.set_item(BINDINGS_STORAGE_KEY, &String::from(text));
+    }
+}
+
+fn check_player(player: usize) -> Result<(), JsValue> {
+    if player >= nes::input::PORTS {
+        return Err(JsValue::from(format!("there is no player {}", player + 1)));
+    }
+    return Ok(());
+}
+
+fn button_index(name: &str) -> Result<usize, JsValue> {
+    return bindings::button_index(name).ok_or_else(|| JsValue::from(format!("unknown button {}", name)));
+}
+
+// the binding table, each player lists the inputs of A, B, Select, Start, Up,
+// Down, Left, Right, TurboA and TurboB
+#[wasm_bindgen]
+pub fn input_bindings() -> Result<JsValue, JsValue> {
+    return BINDINGS.with(|bindings| to_json_value(&*bindings.borrow()));
+}
+
+// players count from 0, source is key:<code>, button:<n>, axis:<n>+ or axis:<n>-
+#[wasm_bindgen]
+pub fn bind_input(player: usize, button: &str, source: &str) -> Result<(), JsValue> {
+    check_player(player)?;
+    let button = button_index(button)?;
+    let source = bindings::Source::try_from(source.to_string()).map_err(JsValue::from)?;
+    BINDINGS.with(|bindings| bindings::bind(&mut bindings.borrow_mut(), player, button, source));
+    save_bindings();
+    return Ok(());
+}
+
+#[wasm_bindgen]
+pub fn clear_input(player: usize, button: &str) -> Result<(), JsValue> {
+    check_player(player)?;
+    let button = button_index(button)?;
+    BINDINGS.with(|bindings| bindings::clear(&mut bindings.borrow_mut(), player, button));
+    save_bindings();
+    return Ok(());
+}
+
+// the index of navigator.getGamepads() the player uses, negative for none
+#[wasm_bindgen]
+pub fn set_player_gamepad(player: usize, gamepad: i32) -> Result<(), JsValue> {
+    check_player(player)?;
+    let gamepad = if gamepad < 0 { None } else { Some(gamepad as u32) };
+    BINDINGS.with(|bindings| bindings::set_gamepad(&mut bindings.borrow_mut(), player, gamepad));
+    save_bindings();
+    return Ok(());
+}
+
+// turbo buttons repeat every period frames, 2 to 8 make sense, and are
+// pressed for on_frames of them
+#[wasm_bindgen]
+pub fn set_turbo(period: u8, on_frames: u8) -> Result<(), JsValue> {
+    if period < 2 || on_frames < 1 || on_frames >= period {
+        return Err(JsValue::from(format!("turbo needs 1 to {} pressed frames of {}", period.max(2) - 1, period)));
+    }
+    BINDINGS.with(|bindings| bindings::set_turbo_rate(&mut bindings.borrow_mut(), period, on_frames));
+    save_bindings();
+    return Ok(());
+}
+
+#[wasm_bindgen]
+pub fn reset_input_bindings() {
+    BINDINGS.with(|bindings| *bindings.borrow_mut() = bindings::default_bindings());
+    save_bindings();
+}
+
+// the key or gamepad input pressed last since the previous call, for binding
+// whatever the user presses next
+#[wasm_bindgen]
+pub fn take_pressed_input() -> Option<String> {
+    return PRESSED_INPUT.with(|pressed| pressed.borrow_mut().take()).map(String::from);
+}
+
+fn listen_keyboard(document: &web_sys::Document) -> Result<(), JsValue> {
+    let keydown = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+        let code = event.code();
+        // keep the arrow keys and space from scrolling the page
+        if code.starts_with("Arrow") || code == "Space" {
+            event.prevent_default();
+        }
+        if !event.repeat() {
+            PRESSED_INPUT.with(|pressed| *pressed.borrow_mut() = Some(bindings::Source::Key(code.clone())));
+        }
+        HELD_KEYS.with(|keys| keys.borrow_mut().insert(code));
+    }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
+    document.add_event_listener_with_callback("keydown", keydown.as_ref().unchecked_ref())?;
+    keydown.forget();
+
+    let keyup = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+        HELD_KEYS.with(|keys| keys.borrow_mut().remove(&event.code()));
+    }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
+    document.add_event_listener_with_callback("keyup", keyup.as_ref().unchecked_ref())?;
+    keyup.forget();
+
+    // the key up events are lost when the window loses focus
+    let release = Closure::wrap(Box::new(move || {
+        HELD_KEYS.with(|keys| keys.borrow_mut().clear());
+    }) as Box<dyn FnMut()>);
+    window().add_event_listener_with_callback("blur", release.as_ref().unchecked_ref())?;
+    release.forget();
+    return Ok(());
+}
+
+// the connected gamepads with their index
+fn poll_gamepads() -> Vec<(u32, bindings::GamepadState)> {
+    let mut states = Vec::new();
+    let gamepads = match window().navigator().get_gamepads() {
+        Ok(gamepads) => gamepads,
+        Err(_) => return states,
+    };
+    for entry in gamepads.iter() {
+        // empty slots are null
+        let gamepad = match entry.dyn_into::<web_sys::Gamepad>() {
+            Ok(gamepad) => gamepad,
+            Err(_) => continue,
+        };
+        if !gamepad.connected() {
+            continue;
+        }
+        let buttons = gamepad.buttons().iter()
+            .map(|button| button.dyn_into::<web_sys::GamepadButton>().map(|button| button.pressed()).unwrap_or(false))
+            .collect();
+        let axes = gamepad.axes().iter().map(|axis| axis.as_f64().unwrap_or(0.0)).collect();
+        states.push((gamepad.index(), bindings::GamepadState { buttons: buttons, axes: axes }));
+    }
+    return states;
+}
+
+// the buttons and turbo buttons of each port from the keyboard and the
+// gamepads, read once per animation frame
+fn read_controllers() -> ([u8; nes::input::PORTS], [u8; nes::input::PORTS]) {
+    let gamepads = poll_gamepads();
+
+    // gamepad inputs that were not active on the previous poll count as pressed
+    let active: Vec<bindings::Source> = gamepads.iter().flat_map(|(_, state)| bindings::active_sources(state)).collect();
+    ACTIVE_GAMEPAD_INPUTS.with(|previous| {
+        let mut previous = previous.borrow_mut();
+        if let Some(source) = active.iter().find(|source| !previous.contains(source)) {
+            PRESSED_INPUT.with(|pressed| *pressed.borrow_mut() = Some(source.clone()));
+        }
+        *previous = active.clone();
+    });
+
+    let mut buttons = [0; nes::input::PORTS];
+    let mut turbo = [0; nes::input::PORTS];
+    HELD_KEYS.with(|keys| BINDINGS.with(|bindings| {
+        let keys = keys.borrow();
+        let bindings = bindings.borrow();
+        for player in 0..nes::input::PORTS {
+            let gamepad = bindings.players[player].gamepad
+                .and_then(|index| gamepads.iter().find(|(connected, _)| *connected == index))
+                .map(|(_, state)| state);
+            let (held, turbo_held) = bindings::buttons(&bindings, player, &keys, gamepad);
+            buttons[player] = held;
+            turbo[player] = turbo_held;
+        }
+    }));
+    return (buttons, turbo);
+}
+
+#[wasm_bindgen]
+pub fn audio_channel_count() -> usize {
+    return SCOPE.with(|scope| scope.borrow().names.len());
+}
+
+#[wasm_bindgen]
+pub fn audio_channel_name(channel: usize) -> String {
+    return SCOPE.with(|scope| scope.borrow().names.get(channel).cloned().unwrap_or_default());
+}
+
+#[wasm_bindgen]
+pub fn set_audio_channel_mute(channel: usize, mute: bool) {
+    MIXER.with(|mixer| nes::apu::set_channel_mute(&mut mixer.borrow_mut(), channel, mute));
+}
+
+#[wasm_bindgen]
+pub fn set_audio_channel_solo(channel: usize, solo: bool) {
+    MIXER.with(|mixer| nes::apu::set_channel_solo(&mut mixer.borrow_mut(), channel, solo));
+}
+
+#[wasm_bindgen]
+pub fn set_audio_channel_volume(channel: usize, volume: f32) {
+    MIXER.with(|mixer| nes::apu::set_channel_volume(&mut mixer.borrow_mut(), channel, volume));
+}
+
+// "nes" mutes the expansion audio of the cartridge, the NES only routes it to the expansion port
+#[wasm_bindgen]
+pub fn set_console_type(name: &str) -> Result<(), JsValue> {
+    let console_type = match name {
+        "famicom" => nes::apu::ConsoleType::Famicom,
+        "nes" => nes::apu::ConsoleType::Nes,
+        _ => return Err(JsValue::from(format!("unknown console type {}", name))),
+    };
+    MIXER.with(|mixer| nes::apu::set_console_type(&mut mixer.borrow_mut(), console_type));
+    return Ok(());
+}
+
+// oscilloscope points of the last frame, returned to JavaScript as a Float32Array
+#[wasm_bindgen]
+pub fn audio_channel_waveform(channel: usize) -> Vec<f32> {
+    return SCOPE.with(|scope| scope.borrow().waveforms.get(channel).cloned().unwrap_or_default());
+}
+
+// false shows every sprite of a line instead of the first 8, which hides the
+// flicker games use to get around the limit
+#[wasm_bindgen]
+pub fn set_sprite_limit(enabled: bool) {
+    SPRITE_LIMIT.with(|limit| limit.set(enabled));
+}
+
+// accepts the contents of a 64 or 512 colour .pal file
+#[wasm_bindgen]
+pub fn load_palette(data: &[u8]) -> Result<(), JsValue> {
+    let table = nes::ppu::palette::load_pal(data).map_err(|e| JsValue::from(e.to_string()))?;
+    DISPLAY.with(|display| video::display::set_palette(&mut display.borrow_mut(), table));
+    return Ok(());
+}
+
+#[wasm_bindgen]
+pub fn set_palette_preset(name: &str) -> Result<(), JsValue> {
+    let preset = match name {
+        "2C02" => nes::ppu::palette::PalettePreset::Rp2C02,
+        "2C03" => nes::ppu::palette::PalettePreset::Rp2C03,
+        _ => return Err(JsValue::from(format!("unknown palette preset {}", name))),
+    };
+    DISPLAY.with(|display| video::display::set_palette(&mut display.borrow_mut(), nes::ppu::palette::preset_palette(preset)));
+    return Ok(());
+}
+
+#[wasm_bindgen]
+pub fn set_ntsc_palette(hue: f64, saturation: f64, contrast: f64, brightness: f64, gamma: f64) {
+    let params = nes::ppu::palette::NtscParameters {
+        hue: hue,
+        saturation: saturation,
+        contrast: contrast,
+        brightness: brightness,
+        gamma: gamma,
+    };
+    DISPLAY.with(|display| video::display::set_ntsc_parameters(&mut display.borrow_mut(), params));
+}
+
+// scale is the number of output pixels per dot (2 or 3), 0 turns the filter off
+#[wasm_bindgen]
+pub fn set_ntsc_filter(scale: usize) {
+    DISPLAY.with(|display| video::display::set_ntsc_filter(&mut display.borrow_mut(), scale));
+}
+
+#[wasm_bindgen]
+pub fn set_scaler(name: &str) -> Result<(), JsValue> {
+    let scaler = video::scale::from_name(name).ok_or_else(|| JsValue::from(format!("unknown scaler {}", name)))?;
+    DISPLAY.with(|display| video::display::set_scaler(&mut display.borrow_mut(), scaler));
+    return Ok(());
+}
+
+// lines and columns to hide at each edge, in NES pixels
+#[wasm_bindgen]
+pub fn set_overscan(top: usize, bottom: usize, left: usize, right: usize) {
+    let overscan = video::display::Overscan {
+        top: top,
+        bottom: bottom,
+        left: left,
+        right: right,
+    };
+    DISPLAY.with(|display| video::display::set_overscan(&mut display.borrow_mut(), overscan));
+}
+
+#[wasm_bindgen]
+pub fn set_aspect_ratio(name: &str) -> Result<(), JsValue> {
+    let aspect = match name {
+        "square" => video::display::AspectRatio::Square,
+        "8:7" => video::display::AspectRatio::PixelAspect8x7,
+        _ => return Err(JsValue::from(format!("unknown aspect ratio {}", name))),
+    };
+    DISPLAY.with(|display| video::display::set_aspect_ratio(&mut display.borrow_mut(), aspect));
+    return Ok(());
+}
+
+#[wasm_bindgen]
+pub fn set_integer_scale(scale: usize) {
+    DISPLAY.with(|display| video::display::set_integer_scale(&mut display.borrow_mut(), scale));
+}
+
+// offers the last frame as a PNG download, raw is the uncropped and unfiltered 256x240 picture
+#[wasm_bindgen]
+pub fn save_screenshot(raw: bool) -> Result<(), JsValue> {
+    let (png, number) = LAST_FRAME.with(|last| {
+        let last = last.borrow();
+        let png = DISPLAY.with(|display| video::display::screenshot(&mut display.borrow_mut(), &last.indices, last.colour_phase, raw, ROM_URL, last.number));
+        return (png, last.number);
+    });
+    let name = ROM_URL.trim_end_matches(".nes");
+    return download(&png, &format!("{}-{}.png", name, number), "image/png");
+}
+
+// an hour of NTSC frames, a replay has to start at power on so the log stops
+// growing there instead of dropping its start
+const MAX_INPUT_LOG_FRAMES: usize = 60 * 60 * 60;
+
+// offers the controller input since power on as a download, the headless
+// runner replays it with --input
+#[wasm_bindgen]
+pub fn save_input_log() -> Result<(), JsValue> {
+    let text = INPUT_LOG.with(|log| record::input_log::format(&log.borrow()));
+    let name = ROM_URL.trim_end_matches(".nes");
+    return download(text.as_bytes(), &format!("{}-input.txt", name), "text/plain");
+}
+
+fn download(data: &[u8], filename: &str, mime_type: &str) -> Result<(), JsValue> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+    let anchor = window().document().unwrap().create_element("a")?.dyn_into::<web_sys::HtmlAnchorElement>()?;
+    anchor.set_href(&url);
+    anchor.set_download(filename);
+    anchor.click();
+    return web_sys::Url::revoke_object_url(&url);
+}
+
 fn window() -> web_sys::Window {
     web_sys::window().expect("no global `window` exists")
 }
 
+// frames to catch up at most after the tab was in the background
+const MAX_FRAME_SKIP: f64 = 4.0;
+
 fn request_animation_frame(f: &Closure<dyn FnMut()>) {
     window()
-        .set_timeout_with_callback_and_timeout_and_arguments_0(f.as_ref().unchecked_ref(), 0)
-        // .request_animation_frame(f.as_ref().unchecked_ref())
+        .request_animation_frame(f.as_ref().unchecked_ref())
         .expect("should register `requestAnimationFrame` OK");
 }
 
-fn render_to_canvas(data: &mut wasm_bindgen::Clamped<Vec<u8>>, context: &web_sys::CanvasRenderingContext2d) {
-    let buffer = web_sys::ImageData::new_with_u8_clamped_array_and_sh(wasm_bindgen::Clamped(data), 256, 240).unwrap();
+fn render_to_canvas(data: &mut [u8], width: u32, height: u32, context: &web_sys::CanvasRenderingContext2d) {
+    let canvas = context.canvas().unwrap();
+    if canvas.width() != width || canvas.height() != height {
+        canvas.set_width(width);
+        canvas.set_height(height);
+    }
+    let buffer = web_sys::ImageData::new_with_u8_clamped_array_and_sh(wasm_bindgen::Clamped(data), width, height).unwrap();
     // context.put_image_data_with_dirty_x_and_dirty_y_and_dirty_width_and_dirty_height(buffer, 0.0, 0.0, 0.0, 0.0, 256.0, 224.0).unwrap();
     context.put_image_data(&buffer, 0.0, 0.0).unwrap();
 }
@@ -110,30 +521,109 @@ pub async fn main_js() -> Result<(), JsValue> {
     let romdata = load_rom().await?;
     let f = Rc::new(RefCell::new(None));
     let g = f.clone();
-    let mut canvas_buffer = context.get_image_data(0.0, 0.0, 256.0, 240.0).unwrap().data().clone();
+    let mut frame_buffer: Vec<u16> = vec![0; 256*240];
+    let mut frame_number: u64 = 0;
+
     let nes_rom = nes::rom::load_nes(&romdata);
     let mut cpu = nes::cpu::new_cpu();
     let mut ppu = nes::ppu::new_ppu(&nes_rom.character_rom.data);
+    nes::ppu::set_mirroring(&mut ppu, nes::rom::get_mirroring(&nes_rom.header));
+    let region = nes::rom::get_region(&nes_rom.header);
+    nes::ppu::set_region(&mut ppu, region);
+    DISPLAY.with(|display| video::display::set_region(&mut display.borrow_mut(), region));
+    let frame_duration = 1000.0 / nes::region::frame_rate(region);
     let mut mem = nes::memory::new_memory(&nes_rom.program_rom.data);
+    if let Some(source) = nes::apu::new_expansion_audio(nes::rom::get_mapper(&nes_rom.header)) {
+        nes::memory::set_expansion_audio(&mut mem, source);
+    }
+    let mut apu = nes::apu::new_apu();
+    nes::apu::set_region(&mut apu, region);
 
-    let mut vmem = nes::vmem::new_vmem(&mut mem, &mut ppu);
+    let mut vmem = nes::vmem::new_vmem(&mut mem, &mut ppu, &mut apu);
     nes::cpu::reset(&mut cpu, &mut vmem);
+    update_scope(&apu);
+
+    let audio = Rc::new(audio::new_audio_output().await.ok());
+    {
+        let audio = audio.clone();
+        let resume = Closure::wrap(Box::new(move || {
+            if let Some(output) = audio.as_ref() {
+                audio::resume(output);
+            }
+        }) as Box<dyn FnMut()>);
+        document.add_event_listener_with_callback("click", resume.as_ref().unchecked_ref())?;
+        document.add_event_listener_with_callback("keydown", resume.as_ref().unchecked_ref())?;
+        resume.forget();
+    }
+    load_bindings();
+    listen_keyboard(&document)?;
+
+    let performance = window().performance().expect("should have `performance` on window");
+    let mut last_time = performance.now();
+    let mut elapsed = 0.0;
+    let mut logged_turbo_rate = None;
 
     *g.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        let mut vmem = nes::vmem::new_vmem(&mut mem, &mut ppu);
-        loop {
-            nes::cpu::run(&mut cpu, &mut vmem);
-            nes::ppu::run(&mut canvas_buffer, &mut vmem.ppu);
-            
-            if nes::ppu::is_draw_timing(vmem.ppu) {
-                render_to_canvas(&mut canvas_buffer, &context);
-                nes::ppu::check_drawn(&mut vmem.ppu);
-
-                // Schedule ourself for another requestAnimationFrame callback.
-                request_animation_frame(f.borrow().as_ref().unwrap());
-                break;
+        // run as many frames as the display refresh covers, so 50/75/144 Hz
+        // displays still see the NES run at its own frame rate
+        let now = performance.now();
+        elapsed += (now - last_time).min(frame_duration * MAX_FRAME_SKIP);
+        last_time = now;
+
+        let (buttons, turbo) = read_controllers();
+        let (turbo_period, turbo_on_frames) = BINDINGS.with(|bindings| {
+            let bindings = bindings.borrow();
+            return (bindings.turbo_period, bindings.turbo_on_frames);
+        });
+        MIXER.with(|mixer| nes::apu::set_mixer(&mut apu, &mixer.borrow()));
+        nes::ppu::set_sprite_limit(&mut ppu, SPRITE_LIMIT.with(|limit| limit.get()));
+        let mut drawn = false;
+        let mut vmem = nes::vmem::new_vmem(&mut mem, &mut ppu, &mut apu);
+        while elapsed >= frame_duration {
+            elapsed -= frame_duration;
+            if let Some(output) = audio.as_ref() {
+                nes::apu::set_sample_rate(&mut vmem.apu, audio::sample_rate(output) * audio::rate_ratio(output));
+            }
+
+            nes::input::set_turbo_rate(&mut vmem.mem.input, turbo_period, turbo_on_frames);
+            for port in 0..nes::input::PORTS {
+                nes::input::set_buttons(&mut vmem.mem.input, port, buttons[port]);
+                nes::input::set_turbo_buttons(&mut vmem.mem.input, port, turbo[port]);
+            }
+            let turbo_rate = Some((turbo_period, turbo_on_frames));
+            let changed = if turbo_rate != logged_turbo_rate { turbo_rate } else { None };
+            logged_turbo_rate = turbo_rate;
+            INPUT_LOG.with(|log| {
+                let mut log = log.borrow_mut();
+                if log.len() < MAX_INPUT_LOG_FRAMES {
+                    log.push(record::input_log::Frame { buttons: buttons, turbo: turbo, turbo_rate: changed });
+                }
+            });
+            nes::system::run_frame(&mut cpu, &mut vmem, &mut frame_buffer);
+            frame_number += 1;
+
+            let samples = nes::apu::take_samples(&mut vmem.apu);
+            if let Some(output) = audio.as_ref() {
+                audio::push_samples(output, &samples);
             }
+            drawn = true;
         }
+
+        if drawn {
+            update_scope(&apu);
+            let colour_phase = nes::ppu::colour_phase(&ppu);
+            LAST_FRAME.with(|last| {
+                let mut last = last.borrow_mut();
+                last.indices.copy_from_slice(&frame_buffer);
+                last.number = frame_number;
+                last.colour_phase = colour_phase;
+            });
+            let (mut image, width, height) = DISPLAY.with(|display| video::display::render(&mut display.borrow_mut(), &frame_buffer, colour_phase));
+            render_to_canvas(&mut image, width as u32, height as u32, &context);
+        }
+
+        // Schedule ourself for another requestAnimationFrame callback.
+        request_animation_frame(f.borrow().as_ref().unwrap());
     }) as Box<dyn FnMut()>));
 
     request_animation_frame(g.borrow().as_ref().unwrap());
@@ -3,3 +3,7 @@ pub mod cpu;
 pub mod memory;
 pub mod vmem;
 pub mod ppu;
+pub mod apu;
+pub mod input;
+pub mod region;
+pub mod system;
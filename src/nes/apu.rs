@@ -0,0 +1,696 @@
+use super::memory;
+use super::region;
+
+mod envelope;
+mod pulse;
+mod triangle;
+mod noise;
+mod dmc;
+mod blip;
+mod filter;
+mod vrc6;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const SAMPLE_BUFFER_LIMIT: usize = 0x4000;
+// output samples to gather in the blip buffer before running them through the filters
+const SAMPLE_CHUNK: usize = 64;
+
+// cpu cycles of the quarter frame steps of the frame counter, the last one is the
+// extra step of the 5-step sequence
+const NTSC_FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+// pulse 1, pulse 2, triangle, noise and dmc, followed by the expansion audio channels
+const APU_CHANNELS: usize = 5;
+const MAX_CHANNELS: usize = 16;
+// cpu cycles between two oscilloscope points
+const SCOPE_DECIMATION: u32 = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ConsoleType {
+    Famicom,
+    // the NES routes the cartridge audio pins to the expansion port only, so expansion audio is muted
+    Nes,
+}
+
+// the console routing and the mute, solo and volume of each channel, changed
+// by the page and handed to the apu between frames
+#[derive(Clone, PartialEq, Debug)]
+pub struct Mixer {
+    console_type: ConsoleType,
+    channel_volume: Vec<f32>,
+    channel_mute: Vec<bool>,
+    channel_solo: Vec<bool>,
+}
+
+pub fn new_mixer() -> Mixer {
+    return Mixer {
+        console_type: ConsoleType::Famicom,
+        channel_volume: vec![1.0; MAX_CHANNELS],
+        channel_mute: vec![false; MAX_CHANNELS],
+        channel_solo: vec![false; MAX_CHANNELS],
+    };
+}
+
+pub struct Apu {
+    pulse1: pulse::Pulse,
+    pulse2: pulse::Pulse,
+    triangle: triangle::Triangle,
+    noise: noise::Noise,
+    dmc: dmc::Dmc,
+    frame_mode: u8,
+    frame_irq_inhibit: bool,
+    frame_irq: bool,
+    frame_cycle: u32,
+    frame_steps: [u32; 5],
+    frame_reset_delay: u8,
+    cycle: u64,
+    cpu_clock: f64,
+    sample_rate: f64,
+    pulse_table: Vec<f32>,
+    tnd_table: Vec<f32>,
+    mixer: Mixer,
+    // gain of each channel worked out from the mixer
+    channel_gain: Vec<f32>,
+    // every 2A03 channel plays at full volume, so the lookup tables give the mix
+    full_volume: bool,
+    blip: blip::Blip,
+    filters: Vec<filter::Filter>,
+    samples: Vec<f32>,
+    expansion_chip: Option<memory::ExpansionChip>,
+    expansion_channels: usize,
+    scope: Vec<Vec<f32>>,
+    scope_frame: Vec<Vec<f32>>,
+    scope_counter: u32,
+}
+
+pub fn new_apu() -> Apu {
+    let mut pulse_table = vec![0.0; 31];
+    for i in 1..31 {
+        pulse_table[i] = 95.52 / (8128.0 / (i as f32) + 100.0);
+    }
+    let mut tnd_table = vec![0.0; 203];
+    for i in 1..203 {
+        tnd_table[i] = 163.67 / (24329.0 / (i as f32) + 100.0);
+    }
+    let sample_rate = DEFAULT_SAMPLE_RATE as f64;
+    return Apu {
+        pulse1: pulse::new_pulse(true),
+        pulse2: pulse::new_pulse(false),
+        triangle: triangle::new_triangle(),
+        noise: noise::new_noise(),
+        dmc: dmc::new_dmc(),
+        frame_mode: 0,
+        frame_irq_inhibit: false,
+        frame_irq: false,
+        frame_cycle: 0,
+        frame_steps: NTSC_FRAME_STEPS,
+        frame_reset_delay: 0,
+        cycle: 0,
+        cpu_clock: region::cpu_clock(region::Region::Ntsc),
+        sample_rate: sample_rate,
+        pulse_table: pulse_table,
+        tnd_table: tnd_table,
+        mixer: new_mixer(),
+        channel_gain: vec![1.0; MAX_CHANNELS],
+        full_volume: true,
+        blip: blip::new_blip(region::cpu_clock(region::Region::Ntsc), sample_rate),
+        filters: vec![
+            filter::new_high_pass(90.0, sample_rate),
+            filter::new_high_pass(440.0, sample_rate),
+            filter::new_low_pass(14000.0, sample_rate),
+        ],
+        samples: Vec::new(),
+        expansion_chip: None,
+        expansion_channels: 0,
+        scope: vec![Vec::new(); MAX_CHANNELS],
+        scope_frame: vec![Vec::new(); MAX_CHANNELS],
+        scope_counter: 0,
+    };
+}
+
+pub fn read_io(apu: &mut Apu, addr: u16) -> u8 {
+    match addr {
+        0x4015 => {
+            // status
+            let mut status = 0u8;
+            if apu.pulse1.length_counter > 0 {
+                status = status | 0x01;
+            }
+            if apu.pulse2.length_counter > 0 {
+                status = status | 0x02;
+            }
+            if apu.triangle.length_counter > 0 {
+                status = status | 0x04;
+            }
+            if apu.noise.length_counter > 0 {
+                status = status | 0x08;
+            }
+            if apu.dmc.bytes_remaining > 0 {
+                status = status | 0x10;
+            }
+            if apu.frame_irq {
+                status = status | 0x40;
+            }
+            if apu.dmc.irq {
+                status = status | 0x80;
+            }
+            apu.frame_irq = false;
+            return status;
+        }
+        _ => {
+        }
+    }
+    return 0;
+}
+
+pub fn write_io(apu: &mut Apu, addr: u16, value: u8) {
+    match addr {
+        0x4000..=0x4003 => {
+            // pulse 1
+            pulse::write_io(&mut apu.pulse1, addr - 0x4000, value);
+        }
+        0x4004..=0x4007 => {
+            // pulse 2
+            pulse::write_io(&mut apu.pulse2, addr - 0x4004, value);
+        }
+        0x4008..=0x400B => {
+            // triangle
+            triangle::write_io(&mut apu.triangle, addr - 0x4008, value);
+        }
+        0x400C..=0x400F => {
+            // noise
+            noise::write_io(&mut apu.noise, addr - 0x400C, value);
+        }
+        0x4010..=0x4013 => {
+            // dmc
+            dmc::write_io(&mut apu.dmc, addr - 0x4010, value);
+        }
+        0x4015 => {
+            // status
+            pulse::set_enabled(&mut apu.pulse1, (value & 0x01) != 0);
+            pulse::set_enabled(&mut apu.pulse2, (value & 0x02) != 0);
+            triangle::set_enabled(&mut apu.triangle, (value & 0x04) != 0);
+            noise::set_enabled(&mut apu.noise, (value & 0x08) != 0);
+            dmc::set_enabled(&mut apu.dmc, (value & 0x10) != 0);
+        }
+        0x4017 => {
+            // frame counter
+            apu.frame_mode = value >> 7;
+            apu.frame_irq_inhibit = (value & 0x40) != 0;
+            if apu.frame_irq_inhibit {
+                apu.frame_irq = false;
+            }
+            // the sequencer restarts 3 or 4 cpu cycles later depending on the apu cycle alignment
+            apu.frame_reset_delay = if (apu.cycle & 1) == 0 { 3 } else { 4 };
+        }
+        _ => {
+        }
+    }
+}
+
+pub fn is_irq(apu: &Apu) -> bool {
+    return apu.frame_irq || apu.dmc.irq;
+}
+
+pub fn dmc_dma_request(apu: &Apu) -> Option<u16> {
+    return dmc::dma_request(&apu.dmc);
+}
+
+pub fn dmc_dma_fill(apu: &mut Apu, value: u8) {
+    dmc::dma_fill(&mut apu.dmc, value);
+}
+
+// dma halts the cpu until it is on a get (even) cycle
+pub fn is_odd_cycle(apu: &Apu) -> bool {
+    return (apu.cycle & 1) != 0;
+}
+
+// Dendy keeps the NTSC frame counter and period tables
+pub fn set_region(apu: &mut Apu, region: region::Region) {
+    let pal = region == region::Region::Pal;
+    apu.frame_steps = if pal { PAL_FRAME_STEPS } else { NTSC_FRAME_STEPS };
+    noise::set_period_table(&mut apu.noise, if pal { &noise::PAL_PERIOD_TABLE } else { &noise::NTSC_PERIOD_TABLE });
+    dmc::set_rate_table(&mut apu.dmc, if pal { &dmc::PAL_RATE_TABLE } else { &dmc::NTSC_RATE_TABLE });
+    apu.cpu_clock = region::cpu_clock(region);
+    let sample_rate = apu.sample_rate;
+    set_sample_rate(apu, sample_rate);
+}
+
+pub fn set_console_type(mixer: &mut Mixer, console_type: ConsoleType) {
+    mixer.console_type = console_type;
+}
+
+// full scale output of each chip relative to the full scale 2A03 output,
+// calibrated against recordings of the chips on Famicom hardware
+fn expansion_volume(chip: memory::ExpansionChip) -> f32 {
+    match chip {
+        memory::ExpansionChip::Vrc6 => 0.915,
+        memory::ExpansionChip::Vrc7 => 0.64,
+        memory::ExpansionChip::Namco163 => 0.9,
+        memory::ExpansionChip::Fds => 0.252,
+        // the two MMC5 pulses match the 2A03 pulses
+        memory::ExpansionChip::Mmc5 => 0.258,
+        memory::ExpansionChip::Sunsoft5b => 0.5,
+    }
+}
+
+// sound chip of the boards with the mapper number, none for the chips not emulated
+pub fn new_expansion_audio(mapper: u16) -> Option<Box<dyn memory::ExpansionAudio>> {
+    match mapper {
+        24 => return Some(Box::new(vrc6::new_vrc6(false))),
+        26 => return Some(Box::new(vrc6::new_vrc6(true))),
+        _ => return None,
+    }
+}
+
+pub fn channel_count(apu: &Apu) -> usize {
+    return APU_CHANNELS + apu.expansion_channels;
+}
+
+pub fn channel_name(apu: &Apu, channel: usize) -> String {
+    match channel {
+        0 => "Pulse 1".to_string(),
+        1 => "Pulse 2".to_string(),
+        2 => "Triangle".to_string(),
+        3 => "Noise".to_string(),
+        4 => "DMC".to_string(),
+        _ => match apu.expansion_chip {
+            Some(chip) if channel < channel_count(apu) => format!("{:?} {}", chip, channel - APU_CHANNELS + 1),
+            _ => String::new(),
+        },
+    }
+}
+
+pub fn set_channel_mute(mixer: &mut Mixer, channel: usize, mute: bool) {
+    if channel < MAX_CHANNELS {
+        mixer.channel_mute[channel] = mute;
+    }
+}
+
+// while any channel is soloed only the soloed channels are heard
+pub fn set_channel_solo(mixer: &mut Mixer, channel: usize, solo: bool) {
+    if channel < MAX_CHANNELS {
+        mixer.channel_solo[channel] = solo;
+    }
+}
+
+pub fn set_channel_volume(mixer: &mut Mixer, channel: usize, volume: f32) {
+    if channel < MAX_CHANNELS {
+        mixer.channel_volume[channel] = volume.max(0.0);
+    }
+}
+
+fn channel_gain(mixer: &Mixer, channel: usize) -> f32 {
+    let audible = if mixer.channel_solo.iter().any(|&solo| solo) {
+        mixer.channel_solo[channel]
+    } else {
+        !mixer.channel_mute[channel]
+    };
+    if !audible {
+        return 0.0;
+    }
+    return mixer.channel_volume[channel];
+}
+
+pub fn set_mixer(apu: &mut Apu, mixer: &Mixer) {
+    if apu.mixer == *mixer {
+        return;
+    }
+    apu.mixer = mixer.clone();
+    for channel in 0..MAX_CHANNELS {
+        apu.channel_gain[channel] = channel_gain(mixer, channel);
+    }
+    apu.full_volume = apu.channel_gain[..APU_CHANNELS].iter().all(|&gain| gain == 1.0);
+}
+
+// waveform of one channel over the last completed frame, scaled to 0.0 - 1.0
+pub fn channel_waveform(apu: &Apu, channel: usize) -> Vec<f32> {
+    if channel >= MAX_CHANNELS {
+        return Vec::new();
+    }
+    return apu.scope_frame[channel].clone();
+}
+
+pub fn end_frame(apu: &mut Apu) {
+    for channel in 0..MAX_CHANNELS {
+        apu.scope_frame[channel].clear();
+        let points = std::mem::replace(&mut apu.scope[channel], Vec::new());
+        apu.scope[channel] = std::mem::replace(&mut apu.scope_frame[channel], points);
+    }
+}
+
+pub fn set_sample_rate(apu: &mut Apu, sample_rate: f64) {
+    apu.sample_rate = sample_rate;
+    blip::set_rates(&mut apu.blip, apu.cpu_clock, sample_rate);
+    for filter in apu.filters.iter_mut() {
+        filter::set_sample_rate(filter, sample_rate);
+    }
+}
+
+pub fn take_samples(apu: &mut Apu) -> Vec<f32> {
+    flush_samples(apu);
+    return std::mem::replace(&mut apu.samples, Vec::new());
+}
+
+fn clock_quarter_frame(apu: &mut Apu) {
+    pulse::clock_quarter_frame(&mut apu.pulse1);
+    pulse::clock_quarter_frame(&mut apu.pulse2);
+    triangle::clock_quarter_frame(&mut apu.triangle);
+    noise::clock_quarter_frame(&mut apu.noise);
+}
+
+fn clock_half_frame(apu: &mut Apu) {
+    pulse::clock_half_frame(&mut apu.pulse1);
+    pulse::clock_half_frame(&mut apu.pulse2);
+    triangle::clock_half_frame(&mut apu.triangle);
+    noise::clock_half_frame(&mut apu.noise);
+}
+
+fn set_frame_irq(apu: &mut Apu) {
+    if !apu.frame_irq_inhibit {
+        apu.frame_irq = true;
+    }
+}
+
+fn clock_frame_counter(apu: &mut Apu) {
+    if apu.frame_reset_delay > 0 {
+        apu.frame_reset_delay -= 1;
+        if apu.frame_reset_delay == 0 {
+            apu.frame_cycle = 0;
+            if apu.frame_mode == 1 {
+                clock_quarter_frame(apu);
+                clock_half_frame(apu);
+            }
+        }
+    }
+
+    apu.frame_cycle += 1;
+    let steps = apu.frame_steps;
+    let cycle = apu.frame_cycle;
+    if cycle == steps[0] || cycle == steps[2] {
+        clock_quarter_frame(apu);
+    } else if cycle == steps[1] {
+        clock_quarter_frame(apu);
+        clock_half_frame(apu);
+    } else if apu.frame_mode == 0 {
+        // 4-step sequence, the irq flag is set on three consecutive cycles
+        if cycle == steps[3] - 1 {
+            set_frame_irq(apu);
+        } else if cycle == steps[3] {
+            clock_quarter_frame(apu);
+            clock_half_frame(apu);
+            set_frame_irq(apu);
+        } else if cycle == steps[3] + 1 {
+            set_frame_irq(apu);
+            apu.frame_cycle = 0;
+        }
+    } else {
+        // 5-step sequence
+        if cycle == steps[4] {
+            clock_quarter_frame(apu);
+            clock_half_frame(apu);
+        } else if cycle == steps[4] + 1 {
+            apu.frame_cycle = 0;
+        }
+    }
+}
+
+// raw output of the 2A03 channels
+fn channel_levels(apu: &Apu) -> [f32; APU_CHANNELS] {
+    return [
+        pulse::output(&apu.pulse1) as f32,
+        pulse::output(&apu.pulse2) as f32,
+        triangle::output(&apu.triangle) as f32,
+        noise::output(&apu.noise) as f32,
+        dmc::output(&apu.dmc) as f32,
+    ];
+}
+
+fn mix(apu: &Apu, expansion: Option<&dyn memory::ExpansionAudio>) -> f32 {
+    let mut output = 0.0;
+    if apu.full_volume {
+        let pulse_out = pulse::output(&apu.pulse1) + pulse::output(&apu.pulse2);
+        let tnd_out = 3 * (triangle::output(&apu.triangle) as usize) + 2 * (noise::output(&apu.noise) as usize) + (dmc::output(&apu.dmc) as usize);
+        output = apu.pulse_table[pulse_out as usize] + apu.tnd_table[tnd_out];
+    } else {
+        // scaled levels fall between the table entries, so the nonlinear dac is worked out
+        let levels = channel_levels(apu);
+        let gain = &apu.channel_gain;
+        let pulse_out = levels[0] * gain[0] + levels[1] * gain[1];
+        let tnd_out = 3.0 * levels[2] * gain[2] + 2.0 * levels[3] * gain[3] + levels[4] * gain[4];
+        if pulse_out > 0.0 {
+            output += 95.52 / (8128.0 / pulse_out + 100.0);
+        }
+        if tnd_out > 0.0 {
+            output += 163.67 / (24329.0 / tnd_out + 100.0);
+        }
+    }
+
+    if let Some(source) = expansion {
+        if apu.mixer.console_type == ConsoleType::Famicom {
+            let mut expansion_out = 0.0;
+            for channel in 0..source.channels().min(MAX_CHANNELS - APU_CHANNELS) {
+                expansion_out += source.output(channel) * apu.channel_gain[APU_CHANNELS + channel];
+            }
+            output += expansion_out * expansion_volume(source.chip());
+        }
+    }
+    return output;
+}
+
+fn capture_scope(apu: &mut Apu, expansion: Option<&dyn memory::ExpansionAudio>) {
+    apu.scope_counter += 1;
+    if apu.scope_counter < SCOPE_DECIMATION {
+        return;
+    }
+    apu.scope_counter = 0;
+
+    let levels = channel_levels(apu);
+    let full_scale = [15.0, 15.0, 15.0, 15.0, 127.0];
+    for channel in 0..APU_CHANNELS {
+        apu.scope[channel].push(levels[channel] / full_scale[channel]);
+    }
+
+    apu.expansion_chip = expansion.map(|source| source.chip());
+    apu.expansion_channels = 0;
+    if let Some(source) = expansion {
+        apu.expansion_channels = source.channels().min(MAX_CHANNELS - APU_CHANNELS);
+        for channel in 0..apu.expansion_channels {
+            apu.scope[APU_CHANNELS + channel].push(source.output(channel));
+        }
+    }
+}
+
+fn flush_samples(apu: &mut Apu) {
+    let start = apu.samples.len();
+    blip::read_samples(&mut apu.blip, &mut apu.samples);
+    for i in start..apu.samples.len() {
+        let mut sample = apu.samples[i];
+        for filter in apu.filters.iter_mut() {
+            sample = filter::process(filter, sample);
+        }
+        apu.samples[i] = sample;
+    }
+    apu.samples.truncate(SAMPLE_BUFFER_LIMIT);
+}
+
+// runs one cpu cycle, the expansion audio source has already been clocked by the cartridge
+pub fn run(apu: &mut Apu, expansion: Option<&dyn memory::ExpansionAudio>) {
+    clock_frame_counter(apu);
+
+    triangle::clock_timer(&mut apu.triangle);
+    noise::clock_timer(&mut apu.noise);
+    dmc::clock_timer(&mut apu.dmc);
+    if (apu.cycle & 1) == 1 {
+        pulse::clock_timer(&mut apu.pulse1);
+        pulse::clock_timer(&mut apu.pulse2);
+    }
+    apu.cycle += 1;
+
+    let output = mix(apu, expansion);
+    capture_scope(apu, expansion);
+    blip::set_level(&mut apu.blip, output);
+    blip::clock(&mut apu.blip);
+    if blip::samples_available(&apu.blip) >= SAMPLE_CHUNK {
+        flush_samples(apu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // runs cpu cycles until the irq line is raised, none if it stays low for limit cycles
+    fn cycles_until_irq(apu: &mut Apu, limit: u32) -> Option<u32> {
+        for cycle in 1..=limit {
+            run(apu, None);
+            if is_irq(apu) {
+                return Some(cycle);
+            }
+        }
+        return None;
+    }
+
+    fn run_cycles(apu: &mut Apu, cycles: u32) {
+        for _ in 0..cycles {
+            run(apu, None);
+        }
+    }
+
+    #[test]
+    fn four_step_sequence_raises_the_frame_irq() {
+        let mut apu = new_apu();
+        assert_eq!(cycles_until_irq(&mut apu, 40000), Some(NTSC_FRAME_STEPS[3] - 1));
+        assert_eq!(read_io(&mut apu, 0x4015) & 0x40, 0x40);
+        assert!(!is_irq(&apu));
+        // the flag is set again on the two cycles after, a read there does not clear it for good
+        run_cycles(&mut apu, 2);
+        assert!(is_irq(&apu));
+        // the sequence restarts on the last of them, 29830 cycles a round
+        read_io(&mut apu, 0x4015);
+        assert_eq!(cycles_until_irq(&mut apu, 40000), Some(NTSC_FRAME_STEPS[3] - 1));
+    }
+
+    #[test]
+    fn five_step_and_inhibited_sequences_stay_quiet() {
+        let mut apu = new_apu();
+        write_io(&mut apu, 0x4017, 0x80);
+        assert_eq!(cycles_until_irq(&mut apu, 80000), None);
+
+        let mut apu = new_apu();
+        run_cycles(&mut apu, NTSC_FRAME_STEPS[3]);
+        assert!(is_irq(&apu));
+        // setting the inhibit flag clears a pending irq
+        write_io(&mut apu, 0x4017, 0x40);
+        assert!(!is_irq(&apu));
+        assert_eq!(cycles_until_irq(&mut apu, 80000), None);
+    }
+
+    // cpu cycles after which pulse 1 lost a length count, a half frame clock each
+    fn half_frame_cycles(apu: &mut Apu, cycles: u32) -> Vec<u32> {
+        let mut clocks = Vec::new();
+        for cycle in 1..=cycles {
+            let length = apu.pulse1.length_counter;
+            run(apu, None);
+            if apu.pulse1.length_counter < length {
+                clocks.push(cycle);
+            }
+        }
+        return clocks;
+    }
+
+    fn new_counting_apu() -> Apu {
+        let mut apu = new_apu();
+        write_io(&mut apu, 0x4015, 0x01);
+        write_io(&mut apu, 0x4003, 0x08);
+        return apu;
+    }
+
+    #[test]
+    fn five_step_sequence_clocks_half_frames_at_steps_2_and_5() {
+        let mut apu = new_counting_apu();
+        write_io(&mut apu, 0x4017, 0x80);
+        // the write clocks one right away, then a round is 37282 cycles
+        let steps = NTSC_FRAME_STEPS;
+        assert_eq!(half_frame_cycles(&mut apu, 80000), vec![
+            3,
+            2 + steps[1],
+            2 + steps[4],
+            3 + steps[4] + steps[1],
+            3 + steps[4] + steps[4],
+        ]);
+    }
+
+    #[test]
+    fn frame_counter_write_takes_effect_3_or_4_cycles_later() {
+        // on an even cycle the sequencer restarts after 3 cycles
+        let mut apu = new_counting_apu();
+        write_io(&mut apu, 0x4017, 0x80);
+        assert_eq!(half_frame_cycles(&mut apu, 4), vec![3]);
+
+        // and after 4 on an odd one
+        let mut apu = new_counting_apu();
+        run_cycles(&mut apu, 1);
+        write_io(&mut apu, 0x4017, 0x80);
+        assert_eq!(half_frame_cycles(&mut apu, 4), vec![4]);
+
+        // the 4-step sequence restarts as late but without the extra clock
+        let mut apu = new_counting_apu();
+        write_io(&mut apu, 0x4017, 0x00);
+        assert_eq!(half_frame_cycles(&mut apu, 4), Vec::<u32>::new());
+        let mut apu = new_counting_apu();
+        write_io(&mut apu, 0x4017, 0x00);
+        assert_eq!(cycles_until_irq(&mut apu, 40000), Some(2 + NTSC_FRAME_STEPS[3] - 1));
+        let mut apu = new_counting_apu();
+        run_cycles(&mut apu, 1);
+        write_io(&mut apu, 0x4017, 0x00);
+        assert_eq!(cycles_until_irq(&mut apu, 40000), Some(3 + NTSC_FRAME_STEPS[3] - 1));
+    }
+
+    #[test]
+    fn length_counter_loads_counts_down_and_halts() {
+        let mut apu = new_apu();
+        // nothing loads while the channel is disabled
+        write_io(&mut apu, 0x4003, 0x08);
+        assert_eq!(read_io(&mut apu, 0x4015) & 0x01, 0);
+        write_io(&mut apu, 0x4015, 0x01);
+        write_io(&mut apu, 0x4003, 0x08);
+        assert_eq!(apu.pulse1.length_counter, 254);
+        assert_eq!(read_io(&mut apu, 0x4015) & 0x01, 0x01);
+        write_io(&mut apu, 0x4015, 0x00);
+        assert_eq!(read_io(&mut apu, 0x4015) & 0x01, 0);
+
+        // 2 frames long, a 5-step write clocks a half frame right away
+        write_io(&mut apu, 0x4015, 0x01);
+        write_io(&mut apu, 0x4003, 0x18);
+        write_io(&mut apu, 0x4017, 0x80);
+        run_cycles(&mut apu, 4);
+        assert_eq!(apu.pulse1.length_counter, 1);
+        run_cycles(&mut apu, NTSC_FRAME_STEPS[1]);
+        assert_eq!(apu.pulse1.length_counter, 0);
+        assert_eq!(read_io(&mut apu, 0x4015) & 0x01, 0);
+
+        write_io(&mut apu, 0x4000, 0x20);
+        write_io(&mut apu, 0x4003, 0x18);
+        run_cycles(&mut apu, NTSC_FRAME_STEPS[4] * 2);
+        assert_eq!(apu.pulse1.length_counter, 2);
+    }
+
+    #[test]
+    fn vrc6_is_mixed_in_on_the_famicom_only() {
+        let mut chip = new_expansion_audio(24).unwrap();
+        // pulse 1 at constant full volume
+        chip.write(0x9000, 0x8F);
+        chip.write(0x9002, 0x80);
+        chip.clock();
+
+        let mut apu = new_apu();
+        let quiet = mix(&apu, None);
+        let expected = 15.0 / 61.0 * expansion_volume(memory::ExpansionChip::Vrc6);
+        assert!((mix(&apu, Some(chip.as_ref())) - quiet - expected).abs() < 1e-6);
+
+        // muting the channel in the mixer takes it out again
+        let mut mixer = new_mixer();
+        set_channel_mute(&mut mixer, APU_CHANNELS, true);
+        set_mixer(&mut apu, &mixer);
+        assert!((mix(&apu, Some(chip.as_ref())) - quiet).abs() < 1e-6);
+
+        let mut mixer = new_mixer();
+        set_console_type(&mut mixer, ConsoleType::Nes);
+        set_mixer(&mut apu, &mixer);
+        assert_eq!(mix(&apu, Some(chip.as_ref())), quiet);
+
+        // the channels show up with the next oscilloscope point
+        for _ in 0..SCOPE_DECIMATION {
+            run(&mut apu, Some(chip.as_ref()));
+        }
+        assert_eq!(channel_count(&apu), APU_CHANNELS + 3);
+        assert_eq!(channel_name(&apu, APU_CHANNELS + 2), "Vrc6 3");
+    }
+}
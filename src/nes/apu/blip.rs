@@ -0,0 +1,176 @@
+use std::f64::consts::PI;
+
+// band-limited step synthesis: every change of the input level is added to the
+// output as a windowed-sinc impulse at its exact sub-sample position, and the
+// impulses are integrated back into a waveform when samples are read.
+
+const PHASES: usize = 64;
+const KERNEL_WIDTH: usize = 32;
+// fraction of the output nyquist frequency let through by the kernel
+const CUTOFF: f64 = 0.9;
+
+pub struct Blip {
+    kernel: Vec<f32>,
+    buffer: Vec<f32>,
+    // output samples per input clock
+    clock_step: f64,
+    // position of the current input clock in output samples, relative to buffer[0]
+    time: f64,
+    level: f32,
+    integrator: f32,
+}
+
+fn build_kernel() -> Vec<f32> {
+    let mut kernel = vec![0.0; PHASES * KERNEL_WIDTH];
+    let half = (KERNEL_WIDTH / 2) as f64;
+    for phase in 0..PHASES {
+        let offset = (phase as f64) / (PHASES as f64);
+        let mut sum = 0.0;
+        let mut taps = [0.0; KERNEL_WIDTH];
+        for i in 0..KERNEL_WIDTH {
+            let x = (i as f64) - half - offset;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * CUTOFF * x).sin() / (PI * CUTOFF * x) };
+            // blackman window over [-half, half]
+            let w = (x + half) / (2.0 * half);
+            let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+            taps[i] = sinc * window;
+            sum += taps[i];
+        }
+        // normalize so that every step integrates to exactly its height
+        for i in 0..KERNEL_WIDTH {
+            kernel[phase * KERNEL_WIDTH + i] = (taps[i] / sum) as f32;
+        }
+    }
+    return kernel;
+}
+
+pub fn new_blip(clock_rate: f64, sample_rate: f64) -> Blip {
+    return Blip {
+        kernel: build_kernel(),
+        buffer: vec![0.0; KERNEL_WIDTH],
+        clock_step: sample_rate / clock_rate,
+        time: 0.0,
+        level: 0.0,
+        integrator: 0.0,
+    };
+}
+
+pub fn set_rates(blip: &mut Blip, clock_rate: f64, sample_rate: f64) {
+    blip.clock_step = sample_rate / clock_rate;
+}
+
+// sets the input level at the current clock
+pub fn set_level(blip: &mut Blip, level: f32) {
+    let delta = level - blip.level;
+    if delta == 0.0 {
+        return;
+    }
+    blip.level = level;
+
+    let position = blip.time as usize;
+    let phase = ((blip.time - (position as f64)) * (PHASES as f64)) as usize;
+    if blip.buffer.len() < position + KERNEL_WIDTH {
+        blip.buffer.resize(position + KERNEL_WIDTH, 0.0);
+    }
+    let kernel = &blip.kernel[phase * KERNEL_WIDTH..(phase + 1) * KERNEL_WIDTH];
+    for i in 0..KERNEL_WIDTH {
+        blip.buffer[position + i] += kernel[i] * delta;
+    }
+}
+
+pub fn clock(blip: &mut Blip) {
+    blip.time += blip.clock_step;
+}
+
+// samples before the current clock can no longer receive impulses
+pub fn samples_available(blip: &Blip) -> usize {
+    return blip.time as usize;
+}
+
+pub fn read_samples(blip: &mut Blip, output: &mut Vec<f32>) {
+    let count = samples_available(blip);
+    if blip.buffer.len() < count + KERNEL_WIDTH {
+        blip.buffer.resize(count + KERNEL_WIDTH, 0.0);
+    }
+    for i in 0..count {
+        blip.integrator += blip.buffer[i];
+        output.push(blip.integrator);
+    }
+    blip.buffer.drain(0..count);
+    blip.time -= count as f64;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::filter;
+
+    const CLOCK_RATE: f64 = 1789773.0;
+    const SAMPLE_RATE: f64 = 48000.0;
+
+    // square wave toggling every `half_period` input clocks
+    fn render_square(half_period: u32, clocks: u32) -> Vec<f32> {
+        let mut blip = new_blip(CLOCK_RATE, SAMPLE_RATE);
+        let mut output = Vec::new();
+        for i in 0..clocks {
+            let level = if (i / half_period) % 2 == 0 { 0.5 } else { -0.5 };
+            set_level(&mut blip, level);
+            clock(&mut blip);
+        }
+        read_samples(&mut blip, &mut output);
+        return output;
+    }
+
+    // magnitude of a single frequency over a hann windowed block, in dB
+    fn magnitude_db(samples: &[f32], frequency: f64) -> f64 {
+        let n = samples.len();
+        let mut re = 0.0;
+        let mut im = 0.0;
+        for i in 0..n {
+            let window = 0.5 - 0.5 * (2.0 * PI * (i as f64) / (n as f64)).cos();
+            let angle = 2.0 * PI * frequency * (i as f64) / SAMPLE_RATE;
+            re += (samples[i] as f64) * window * angle.cos();
+            im += (samples[i] as f64) * window * angle.sin();
+        }
+        // hann window has a coherent gain of 0.5
+        let amplitude = 2.0 * (re * re + im * im).sqrt() / (0.5 * (n as f64));
+        return 20.0 * amplitude.log10();
+    }
+
+    #[test]
+    fn square_wave_fundamental_is_preserved() {
+        // 1789773 / 2 / 895 = 999.9 Hz
+        let frequency = CLOCK_RATE / (2.0 * 895.0);
+        let samples = render_square(895, 1789773);
+        let expected = 20.0 * (4.0 / PI * 0.5f64).log10();
+        let measured = magnitude_db(&samples[1024..9216], frequency);
+        assert!((measured - expected).abs() < 0.5, "fundamental {} dB, expected {} dB", measured, expected);
+    }
+
+    #[test]
+    fn harmonics_above_nyquist_do_not_alias() {
+        // 10 kHz square, its 5th harmonic at 50.3 kHz would alias to 2.3 kHz
+        let frequency = CLOCK_RATE / (2.0 * 89.0);
+        let alias = 5.0 * frequency - SAMPLE_RATE;
+        let samples = render_square(89, 1789773);
+        let fundamental = magnitude_db(&samples[1024..9216], frequency);
+        let aliased = magnitude_db(&samples[1024..9216], alias);
+        assert!(fundamental - aliased > 70.0, "alias only {} dB below the fundamental", fundamental - aliased);
+    }
+
+    #[test]
+    fn filter_chain_shapes_the_spectrum() {
+        let mut high_pass = filter::new_high_pass(90.0, SAMPLE_RATE);
+        let mut low_pass = filter::new_low_pass(14000.0, SAMPLE_RATE);
+        let mut low = Vec::new();
+        let mut high = Vec::new();
+        for i in 0..16384 {
+            let t = (i as f64) / SAMPLE_RATE;
+            low.push(filter::process(&mut high_pass, (2.0 * PI * 20.0 * t).sin() as f32));
+            high.push(filter::process(&mut low_pass, (2.0 * PI * 20000.0 * t).sin() as f32));
+        }
+        // 0 dB input, first order roll-off below 90 Hz and above 14 kHz
+        assert!(magnitude_db(&low[8192..16384], 20.0) < -12.0);
+        assert!(magnitude_db(&high[8192..16384], 20000.0) < -3.0);
+    }
+}
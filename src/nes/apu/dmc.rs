@@ -0,0 +1,147 @@
+// in cpu cycles
+pub const NTSC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+pub const PAL_RATE_TABLE: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+pub struct Dmc {
+    pub irq: bool,
+    irq_enabled: bool,
+    loop_flag: bool,
+    rate_table: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    pub bytes_remaining: u16,
+    sample_buffer: u8,
+    sample_buffer_empty: bool,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+pub fn new_dmc() -> Dmc {
+    return Dmc {
+        irq: false,
+        irq_enabled: false,
+        loop_flag: false,
+        rate_table: &NTSC_RATE_TABLE,
+        timer_period: NTSC_RATE_TABLE[0],
+        timer: 0,
+        output_level: 0,
+        sample_address: 0xC000,
+        sample_length: 1,
+        current_address: 0xC000,
+        bytes_remaining: 0,
+        sample_buffer: 0,
+        sample_buffer_empty: true,
+        shift_register: 0,
+        bits_remaining: 8,
+        silence: true,
+    };
+}
+
+pub fn write_io(dmc: &mut Dmc, reg: u16, value: u8) {
+    match reg {
+        0 => {
+            dmc.irq_enabled = (value & 0x80) != 0;
+            if !dmc.irq_enabled {
+                dmc.irq = false;
+            }
+            dmc.loop_flag = (value & 0x40) != 0;
+            dmc.timer_period = dmc.rate_table[(value & 0x0F) as usize];
+        }
+        1 => {
+            dmc.output_level = value & 0x7F;
+        }
+        2 => {
+            dmc.sample_address = 0xC000 | ((value as u16) << 6);
+        }
+        3 => {
+            dmc.sample_length = ((value as u16) << 4) | 1;
+        }
+        _ => {
+        }
+    }
+}
+
+fn restart(dmc: &mut Dmc) {
+    dmc.current_address = dmc.sample_address;
+    dmc.bytes_remaining = dmc.sample_length;
+}
+
+pub fn set_rate_table(dmc: &mut Dmc, table: &'static [u16; 16]) {
+    dmc.rate_table = table;
+}
+
+pub fn set_enabled(dmc: &mut Dmc, enabled: bool) {
+    dmc.irq = false;
+    if !enabled {
+        dmc.bytes_remaining = 0;
+    } else if dmc.bytes_remaining == 0 {
+        restart(dmc);
+    }
+}
+
+// address the memory reader wants to fetch, the bus performs the read and stalls the cpu
+pub fn dma_request(dmc: &Dmc) -> Option<u16> {
+    if dmc.sample_buffer_empty && dmc.bytes_remaining > 0 {
+        return Some(dmc.current_address);
+    }
+    return None;
+}
+
+pub fn dma_fill(dmc: &mut Dmc, value: u8) {
+    dmc.sample_buffer = value;
+    dmc.sample_buffer_empty = false;
+    dmc.current_address = if dmc.current_address == 0xFFFF { 0x8000 } else { dmc.current_address + 1 };
+    dmc.bytes_remaining -= 1;
+    if dmc.bytes_remaining == 0 {
+        if dmc.loop_flag {
+            restart(dmc);
+        } else if dmc.irq_enabled {
+            dmc.irq = true;
+        }
+    }
+}
+
+// clocked every cpu cycle
+pub fn clock_timer(dmc: &mut Dmc) {
+    if dmc.timer > 0 {
+        dmc.timer -= 1;
+        return;
+    }
+    dmc.timer = dmc.timer_period - 1;
+
+    if !dmc.silence {
+        if (dmc.shift_register & 1) != 0 {
+            if dmc.output_level <= 125 {
+                dmc.output_level += 2;
+            }
+        } else if dmc.output_level >= 2 {
+            dmc.output_level -= 2;
+        }
+    }
+    dmc.shift_register = dmc.shift_register >> 1;
+
+    dmc.bits_remaining -= 1;
+    if dmc.bits_remaining == 0 {
+        dmc.bits_remaining = 8;
+        if dmc.sample_buffer_empty {
+            dmc.silence = true;
+        } else {
+            dmc.silence = false;
+            dmc.shift_register = dmc.sample_buffer;
+            dmc.sample_buffer_empty = true;
+        }
+    }
+}
+
+pub fn output(dmc: &Dmc) -> u8 {
+    return dmc.output_level;
+}
@@ -0,0 +1,51 @@
+pub struct Envelope {
+    pub start: bool,
+    pub loop_flag: bool,
+    pub constant_volume: bool,
+    pub volume: u8,
+    divider: u8,
+    decay_level: u8,
+}
+
+pub fn new_envelope() -> Envelope {
+    return Envelope {
+        start: false,
+        loop_flag: false,
+        constant_volume: false,
+        volume: 0,
+        divider: 0,
+        decay_level: 0,
+    };
+}
+
+pub fn write_control(envelope: &mut Envelope, value: u8) {
+    envelope.loop_flag = (value & 0x20) != 0;
+    envelope.constant_volume = (value & 0x10) != 0;
+    envelope.volume = value & 0x0F;
+}
+
+pub fn clock(envelope: &mut Envelope) {
+    if envelope.start {
+        envelope.start = false;
+        envelope.decay_level = 15;
+        envelope.divider = envelope.volume;
+        return;
+    }
+    if envelope.divider > 0 {
+        envelope.divider -= 1;
+        return;
+    }
+    envelope.divider = envelope.volume;
+    if envelope.decay_level > 0 {
+        envelope.decay_level -= 1;
+    } else if envelope.loop_flag {
+        envelope.decay_level = 15;
+    }
+}
+
+pub fn output(envelope: &Envelope) -> u8 {
+    if envelope.constant_volume {
+        return envelope.volume;
+    }
+    return envelope.decay_level;
+}
@@ -0,0 +1,47 @@
+use std::f64::consts::PI;
+
+// first order filters modelling the RC stages between the 2A03 and the audio output
+pub struct Filter {
+    high_pass: bool,
+    cutoff: f64,
+    alpha: f32,
+    prev_input: f32,
+    prev_output: f32,
+}
+
+fn new_filter(high_pass: bool, cutoff: f64, sample_rate: f64) -> Filter {
+    let mut filter = Filter {
+        high_pass: high_pass,
+        cutoff: cutoff,
+        alpha: 0.0,
+        prev_input: 0.0,
+        prev_output: 0.0,
+    };
+    set_sample_rate(&mut filter, sample_rate);
+    return filter;
+}
+
+pub fn new_high_pass(cutoff: f64, sample_rate: f64) -> Filter {
+    return new_filter(true, cutoff, sample_rate);
+}
+
+pub fn new_low_pass(cutoff: f64, sample_rate: f64) -> Filter {
+    return new_filter(false, cutoff, sample_rate);
+}
+
+pub fn set_sample_rate(filter: &mut Filter, sample_rate: f64) {
+    let rc = 1.0 / (2.0 * PI * filter.cutoff);
+    let dt = 1.0 / sample_rate;
+    filter.alpha = if filter.high_pass { rc / (rc + dt) } else { dt / (rc + dt) } as f32;
+}
+
+pub fn process(filter: &mut Filter, input: f32) -> f32 {
+    let output = if filter.high_pass {
+        filter.alpha * (filter.prev_output + input - filter.prev_input)
+    } else {
+        filter.prev_output + filter.alpha * (input - filter.prev_output)
+    };
+    filter.prev_input = input;
+    filter.prev_output = output;
+    return output;
+}
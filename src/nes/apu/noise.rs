@@ -0,0 +1,97 @@
+use super::envelope;
+
+// in cpu cycles
+pub const NTSC_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+pub const PAL_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+pub struct Noise {
+    pub enabled: bool,
+    mode: bool,
+    shift_register: u16,
+    period_table: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    pub length_counter: u8,
+    length_halt: bool,
+    envelope: envelope::Envelope,
+}
+
+pub fn new_noise() -> Noise {
+    return Noise {
+        enabled: false,
+        mode: false,
+        shift_register: 1,
+        period_table: &NTSC_PERIOD_TABLE,
+        timer_period: NTSC_PERIOD_TABLE[0],
+        timer: 0,
+        length_counter: 0,
+        length_halt: false,
+        envelope: envelope::new_envelope(),
+    };
+}
+
+pub fn write_io(noise: &mut Noise, reg: u16, value: u8) {
+    match reg {
+        0 => {
+            noise.length_halt = (value & 0x20) != 0;
+            envelope::write_control(&mut noise.envelope, value);
+        }
+        2 => {
+            noise.mode = (value & 0x80) != 0;
+            noise.timer_period = noise.period_table[(value & 0x0F) as usize];
+        }
+        3 => {
+            if noise.enabled {
+                noise.length_counter = super::LENGTH_TABLE[(value >> 3) as usize];
+            }
+            noise.envelope.start = true;
+        }
+        _ => {
+        }
+    }
+}
+
+pub fn set_period_table(noise: &mut Noise, table: &'static [u16; 16]) {
+    noise.period_table = table;
+}
+
+pub fn set_enabled(noise: &mut Noise, enabled: bool) {
+    noise.enabled = enabled;
+    if !enabled {
+        noise.length_counter = 0;
+    }
+}
+
+// clocked every cpu cycle
+pub fn clock_timer(noise: &mut Noise) {
+    if noise.timer > 0 {
+        noise.timer -= 1;
+        return;
+    }
+    noise.timer = noise.timer_period - 1;
+
+    let tap = if noise.mode { 6 } else { 1 };
+    let feedback = (noise.shift_register ^ (noise.shift_register >> tap)) & 1;
+    noise.shift_register = (noise.shift_register >> 1) | (feedback << 14);
+}
+
+pub fn clock_quarter_frame(noise: &mut Noise) {
+    envelope::clock(&mut noise.envelope);
+}
+
+pub fn clock_half_frame(noise: &mut Noise) {
+    if !noise.length_halt && noise.length_counter > 0 {
+        noise.length_counter -= 1;
+    }
+}
+
+pub fn output(noise: &Noise) -> u8 {
+    if noise.length_counter == 0 || (noise.shift_register & 1) != 0 {
+        return 0;
+    }
+    return envelope::output(&noise.envelope);
+}
@@ -0,0 +1,140 @@
+use super::envelope;
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+pub struct Pulse {
+    // pulse 1 adds the carry in ones' complement when the sweep negates
+    ones_complement: bool,
+    pub enabled: bool,
+    duty: u8,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    pub length_counter: u8,
+    length_halt: bool,
+    envelope: envelope::Envelope,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+pub fn new_pulse(ones_complement: bool) -> Pulse {
+    return Pulse {
+        ones_complement: ones_complement,
+        enabled: false,
+        duty: 0,
+        sequence: 0,
+        timer_period: 0,
+        timer: 0,
+        length_counter: 0,
+        length_halt: false,
+        envelope: envelope::new_envelope(),
+        sweep_enabled: false,
+        sweep_period: 0,
+        sweep_negate: false,
+        sweep_shift: 0,
+        sweep_reload: false,
+        sweep_divider: 0,
+    };
+}
+
+pub fn write_io(pulse: &mut Pulse, reg: u16, value: u8) {
+    match reg {
+        0 => {
+            pulse.duty = value >> 6;
+            pulse.length_halt = (value & 0x20) != 0;
+            envelope::write_control(&mut pulse.envelope, value);
+        }
+        1 => {
+            pulse.sweep_enabled = (value & 0x80) != 0;
+            pulse.sweep_period = (value >> 4) & 0x07;
+            pulse.sweep_negate = (value & 0x08) != 0;
+            pulse.sweep_shift = value & 0x07;
+            pulse.sweep_reload = true;
+        }
+        2 => {
+            pulse.timer_period = (pulse.timer_period & 0x0700) | (value as u16);
+        }
+        3 => {
+            pulse.timer_period = (pulse.timer_period & 0x00FF) | (((value & 0x07) as u16) << 8);
+            if pulse.enabled {
+                pulse.length_counter = super::LENGTH_TABLE[(value >> 3) as usize];
+            }
+            pulse.sequence = 0;
+            pulse.envelope.start = true;
+        }
+        _ => {
+        }
+    }
+}
+
+pub fn set_enabled(pulse: &mut Pulse, enabled: bool) {
+    pulse.enabled = enabled;
+    if !enabled {
+        pulse.length_counter = 0;
+    }
+}
+
+fn sweep_target(pulse: &Pulse) -> u16 {
+    let change = pulse.timer_period >> pulse.sweep_shift;
+    if pulse.sweep_negate {
+        if pulse.ones_complement {
+            return pulse.timer_period.wrapping_sub(change).wrapping_sub(1);
+        }
+        return pulse.timer_period.wrapping_sub(change);
+    }
+    return pulse.timer_period + change;
+}
+
+fn is_muted(pulse: &Pulse) -> bool {
+    let target = sweep_target(pulse);
+    return pulse.timer_period < 8 || (target > 0x07FF && target < 0x8000);
+}
+
+// clocked every other cpu cycle
+pub fn clock_timer(pulse: &mut Pulse) {
+    if pulse.timer == 0 {
+        pulse.timer = pulse.timer_period;
+        pulse.sequence = (pulse.sequence + 1) & 0x07;
+    } else {
+        pulse.timer -= 1;
+    }
+}
+
+pub fn clock_quarter_frame(pulse: &mut Pulse) {
+    envelope::clock(&mut pulse.envelope);
+}
+
+pub fn clock_half_frame(pulse: &mut Pulse) {
+    if !pulse.length_halt && pulse.length_counter > 0 {
+        pulse.length_counter -= 1;
+    }
+
+    if pulse.sweep_divider == 0 && pulse.sweep_enabled && pulse.sweep_shift > 0 && !is_muted(pulse) {
+        pulse.timer_period = sweep_target(pulse);
+    }
+    if pulse.sweep_divider == 0 || pulse.sweep_reload {
+        pulse.sweep_divider = pulse.sweep_period;
+        pulse.sweep_reload = false;
+    } else {
+        pulse.sweep_divider -= 1;
+    }
+}
+
+pub fn output(pulse: &Pulse) -> u8 {
+    if pulse.length_counter == 0 || is_muted(pulse) {
+        return 0;
+    }
+    if DUTY_TABLE[pulse.duty as usize][pulse.sequence as usize] == 0 {
+        return 0;
+    }
+    return envelope::output(&pulse.envelope);
+}
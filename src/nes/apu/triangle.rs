@@ -0,0 +1,92 @@
+const SEQUENCE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+pub struct Triangle {
+    pub enabled: bool,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    pub length_counter: u8,
+    // doubles as the length counter halt flag
+    control: bool,
+    linear_counter: u8,
+    linear_reload_value: u8,
+    linear_reload: bool,
+}
+
+pub fn new_triangle() -> Triangle {
+    return Triangle {
+        enabled: false,
+        sequence: 0,
+        timer_period: 0,
+        timer: 0,
+        length_counter: 0,
+        control: false,
+        linear_counter: 0,
+        linear_reload_value: 0,
+        linear_reload: false,
+    };
+}
+
+pub fn write_io(triangle: &mut Triangle, reg: u16, value: u8) {
+    match reg {
+        0 => {
+            triangle.control = (value & 0x80) != 0;
+            triangle.linear_reload_value = value & 0x7F;
+        }
+        2 => {
+            triangle.timer_period = (triangle.timer_period & 0x0700) | (value as u16);
+        }
+        3 => {
+            triangle.timer_period = (triangle.timer_period & 0x00FF) | (((value & 0x07) as u16) << 8);
+            if triangle.enabled {
+                triangle.length_counter = super::LENGTH_TABLE[(value >> 3) as usize];
+            }
+            triangle.linear_reload = true;
+        }
+        _ => {
+        }
+    }
+}
+
+pub fn set_enabled(triangle: &mut Triangle, enabled: bool) {
+    triangle.enabled = enabled;
+    if !enabled {
+        triangle.length_counter = 0;
+    }
+}
+
+// clocked every cpu cycle
+pub fn clock_timer(triangle: &mut Triangle) {
+    if triangle.timer == 0 {
+        triangle.timer = triangle.timer_period;
+        if triangle.length_counter > 0 && triangle.linear_counter > 0 {
+            triangle.sequence = (triangle.sequence + 1) & 0x1F;
+        }
+    } else {
+        triangle.timer -= 1;
+    }
+}
+
+pub fn clock_quarter_frame(triangle: &mut Triangle) {
+    if triangle.linear_reload {
+        triangle.linear_counter = triangle.linear_reload_value;
+    } else if triangle.linear_counter > 0 {
+        triangle.linear_counter -= 1;
+    }
+    if !triangle.control {
+        triangle.linear_reload = false;
+    }
+}
+
+pub fn clock_half_frame(triangle: &mut Triangle) {
+    if !triangle.control && triangle.length_counter > 0 {
+        triangle.length_counter -= 1;
+    }
+}
+
+pub fn output(triangle: &Triangle) -> u8 {
+    return SEQUENCE_TABLE[triangle.sequence as usize];
+}
@@ -0,0 +1,290 @@
+use super::super::memory;
+
+// sum of the two pulse volumes and the top sawtooth level
+const FULL_SCALE: f32 = 61.0;
+
+struct Pulse {
+    // ignores the duty and outputs the volume all the time
+    constant: bool,
+    duty: u8,
+    volume: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    // counts down from 15, the output is high while it is at or below the duty
+    step: u8,
+}
+
+fn new_pulse() -> Pulse {
+    return Pulse {
+        constant: false,
+        duty: 0,
+        volume: 0,
+        enabled: false,
+        period: 0,
+        timer: 0,
+        step: 15,
+    };
+}
+
+struct Sawtooth {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    // the accumulator takes the rate on every second step and clears after the 14th
+    step: u8,
+    accumulator: u8,
+}
+
+fn new_sawtooth() -> Sawtooth {
+    return Sawtooth {
+        rate: 0,
+        enabled: false,
+        period: 0,
+        timer: 0,
+        step: 0,
+        accumulator: 0,
+    };
+}
+
+pub struct Vrc6 {
+    // mapper 26 swaps the A0 and A1 lines of the registers
+    swapped_lines: bool,
+    pulse1: Pulse,
+    pulse2: Pulse,
+    sawtooth: Sawtooth,
+    halt: bool,
+    // the timers run on the period shifted right by 0, 4 or 8
+    period_shift: u8,
+}
+
+pub fn new_vrc6(swapped_lines: bool) -> Vrc6 {
+    return Vrc6 {
+        swapped_lines: swapped_lines,
+        pulse1: new_pulse(),
+        pulse2: new_pulse(),
+        sawtooth: new_sawtooth(),
+        halt: false,
+        period_shift: 0,
+    };
+}
+
+fn write_pulse(pulse: &mut Pulse, reg: u16, value: u8) {
+    match reg {
+        0 => {
+            pulse.constant = (value & 0x80) != 0;
+            pulse.duty = (value >> 4) & 0x07;
+            pulse.volume = value & 0x0F;
+        }
+        1 => {
+            pulse.period = (pulse.period & 0x0F00) | (value as u16);
+        }
+        2 => {
+            pulse.period = (pulse.period & 0x00FF) | (((value & 0x0F) as u16) << 8);
+            pulse.enabled = (value & 0x80) != 0;
+            if !pulse.enabled {
+                pulse.step = 15;
+            }
+        }
+        _ => {
+        }
+    }
+}
+
+fn write_sawtooth(sawtooth: &mut Sawtooth, reg: u16, value: u8) {
+    match reg {
+        0 => {
+            sawtooth.rate = value & 0x3F;
+        }
+        1 => {
+            sawtooth.period = (sawtooth.period & 0x0F00) | (value as u16);
+        }
+        2 => {
+            sawtooth.period = (sawtooth.period & 0x00FF) | (((value & 0x0F) as u16) << 8);
+            sawtooth.enabled = (value & 0x80) != 0;
+            if !sawtooth.enabled {
+                sawtooth.step = 0;
+                sawtooth.accumulator = 0;
+            }
+        }
+        _ => {
+        }
+    }
+}
+
+fn clock_pulse(pulse: &mut Pulse, period_shift: u8) {
+    if !pulse.enabled {
+        return;
+    }
+    if pulse.timer == 0 {
+        pulse.timer = pulse.period >> period_shift;
+        pulse.step = pulse.step.wrapping_sub(1) & 0x0F;
+    } else {
+        pulse.timer -= 1;
+    }
+}
+
+fn clock_sawtooth(sawtooth: &mut Sawtooth, period_shift: u8) {
+    if !sawtooth.enabled {
+        return;
+    }
+    if sawtooth.timer == 0 {
+        sawtooth.timer = sawtooth.period >> period_shift;
+        sawtooth.step += 1;
+        if sawtooth.step == 14 {
+            sawtooth.step = 0;
+            sawtooth.accumulator = 0;
+        } else if (sawtooth.step & 1) == 0 {
+            sawtooth.accumulator = sawtooth.accumulator.wrapping_add(sawtooth.rate);
+        }
+    } else {
+        sawtooth.timer -= 1;
+    }
+}
+
+fn pulse_output(pulse: &Pulse) -> u8 {
+    if !pulse.enabled {
+        return 0;
+    }
+    if pulse.constant || pulse.step <= pulse.duty {
+        return pulse.volume;
+    }
+    return 0;
+}
+
+fn sawtooth_output(sawtooth: &Sawtooth) -> u8 {
+    return sawtooth.accumulator >> 3;
+}
+
+impl memory::ExpansionAudio for Vrc6 {
+    fn chip(&self) -> memory::ExpansionChip {
+        return memory::ExpansionChip::Vrc6;
+    }
+
+    fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+        clock_pulse(&mut self.pulse1, self.period_shift);
+        clock_pulse(&mut self.pulse2, self.period_shift);
+        clock_sawtooth(&mut self.sawtooth, self.period_shift);
+    }
+
+    fn channels(&self) -> usize {
+        return 3;
+    }
+
+    fn output(&self, channel: usize) -> f32 {
+        let level = match channel {
+            0 => pulse_output(&self.pulse1),
+            1 => pulse_output(&self.pulse2),
+            2 => sawtooth_output(&self.sawtooth),
+            _ => 0,
+        };
+        return (level as f32) / FULL_SCALE;
+    }
+
+    // the registers are write only
+    fn read(&mut self, _addr: u16) -> Option<u8> {
+        return None;
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let mut addr = addr & 0xF003;
+        if self.swapped_lines {
+            addr = (addr & 0xF000) | ((addr & 0x0001) << 1) | ((addr & 0x0002) >> 1);
+        }
+        let reg = addr & 0x0003;
+        match addr & 0xF000 {
+            0x9000 if reg == 3 => {
+                self.halt = (value & 0x01) != 0;
+                // the 256x bit takes priority over the 16x bit
+                self.period_shift = if (value & 0x04) != 0 { 8 } else if (value & 0x02) != 0 { 4 } else { 0 };
+            }
+            0x9000 => write_pulse(&mut self.pulse1, reg, value),
+            0xA000 => write_pulse(&mut self.pulse2, reg, value),
+            0xB000 => write_sawtooth(&mut self.sawtooth, reg, value),
+            _ => {
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::memory::ExpansionAudio;
+
+    // level of one channel over the given cpu cycles, in steps of the full scale
+    fn levels(vrc6: &mut Vrc6, channel: usize, cycles: usize) -> Vec<u8> {
+        let mut levels = Vec::new();
+        for _ in 0..cycles {
+            vrc6.clock();
+            levels.push((vrc6.output(channel) * FULL_SCALE).round() as u8);
+        }
+        return levels;
+    }
+
+    #[test]
+    fn pulse_is_high_for_duty_plus_one_of_16_steps() {
+        let mut vrc6 = new_vrc6(false);
+        // duty 3, volume 9, a step every cycle
+        vrc6.write(0x9000, 0x39);
+        vrc6.write(0x9001, 0x00);
+        vrc6.write(0x9002, 0x80);
+        let levels = levels(&mut vrc6, 0, 32);
+        assert_eq!(levels[..16], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9, 9, 9, 9, 0]);
+        assert_eq!(levels[..16], levels[16..]);
+    }
+
+    #[test]
+    fn constant_mode_and_disable() {
+        let mut vrc6 = new_vrc6(false);
+        vrc6.write(0xA000, 0x8F);
+        vrc6.write(0xA002, 0x80);
+        assert!(levels(&mut vrc6, 1, 16).iter().all(|&level| level == 15));
+        vrc6.write(0xA002, 0x00);
+        assert!(levels(&mut vrc6, 1, 16).iter().all(|&level| level == 0));
+    }
+
+    #[test]
+    fn sawtooth_ramps_in_seven_levels() {
+        let mut vrc6 = new_vrc6(false);
+        vrc6.write(0xB000, 42);
+        vrc6.write(0xB001, 0x00);
+        vrc6.write(0xB002, 0x80);
+        let levels = levels(&mut vrc6, 2, 28);
+        assert_eq!(levels[..14], [0, 5, 5, 10, 10, 15, 15, 21, 21, 26, 26, 31, 31, 0]);
+        assert_eq!(levels[..14], levels[14..]);
+    }
+
+    #[test]
+    fn frequency_control_halts_and_shifts_the_period() {
+        let mut vrc6 = new_vrc6(false);
+        vrc6.write(0x9000, 0x0F);
+        vrc6.write(0x9001, 0x0F);
+        vrc6.write(0x9002, 0x80);
+        vrc6.write(0x9003, 0x01);
+        vrc6.clock();
+        assert_eq!(vrc6.pulse1.step, 15);
+        // a period of 15 shifted by 4 steps every cycle
+        vrc6.write(0x9003, 0x02);
+        vrc6.clock();
+        vrc6.clock();
+        assert_eq!(vrc6.pulse1.step, 13);
+        // 256x wins over 16x
+        vrc6.write(0x9003, 0x06);
+        assert_eq!(vrc6.period_shift, 8);
+    }
+
+    #[test]
+    fn mapper_26_swaps_the_low_address_lines() {
+        let mut vrc6 = new_vrc6(true);
+        // $9001 reaches the high period and enable register on mapper 26
+        vrc6.write(0x9001, 0x85);
+        vrc6.write(0x9002, 0x34);
+        assert!(vrc6.pulse1.enabled);
+        assert_eq!(vrc6.pulse1.period, 0x0534);
+    }
+}
@@ -1,5 +1,4 @@
 use super::vmem;
-use web_sys::console;
 
 mod opcode;
 
@@ -11,6 +10,8 @@ pub struct Cpu {
     pub reg_p: u8,
     pub reg_pc: u16,
     pub cycle: i16,
+    // cycles of the instruction or interrupt being run
+    pub length: i16,
 }
 
 pub fn new_cpu() -> Cpu {
@@ -22,6 +23,7 @@ pub fn new_cpu() -> Cpu {
         reg_p: REG_P_FLAG_I | REG_P_FLAG_R,
         reg_pc: 0x8000,
         cycle: 0,
+        length: 0,
     };
 }
 
@@ -69,6 +71,19 @@ fn write_word(data: &mut Vec<u8>, p: u16, v: u16) {
     data[(p + 1) as usize] = ((v & 0xFF00) >> 8) as u8;
 }
 
+fn interrupt(cpu: &mut Cpu, mem: &mut vmem::Vmem, vector: u16) {
+    stack_push_word(cpu, mem, cpu.reg_pc);
+    stack_push_byte(cpu, mem, cpu.reg_p & REG_P_MASK_B);
+    cpu.reg_p = cpu.reg_p | REG_P_FLAG_I;
+    cpu.reg_pc = vmem::read_mem_word(mem, vector);
+    cpu.cycle = 7;
+    cpu.length = 7;
+}
+
+pub fn stall(cpu: &mut Cpu, cycles: u16) {
+    cpu.cycle = cpu.cycle + (cycles as i16);
+}
+
 fn stack_push_byte(cpu: &mut Cpu, mem: &mut vmem::Vmem, data: u8) {
     let stack_addr = 0x0100 | (cpu.reg_s as u16);
     // println!("stack push byte p={:04X} v={:04X}", stack_addr, data);
@@ -114,18 +129,32 @@ pub fn run(cpu: &mut Cpu, mem: &mut vmem::Vmem) {
     }
     cpu.reg_p = cpu.reg_p | REG_P_FLAG_R;
 
+    if vmem::take_nmi(mem) {
+        interrupt(cpu, mem, 0xFFFA);
+        return;
+    }
+    if vmem::is_irq(mem) && (cpu.reg_p & REG_P_FLAG_I) == 0 {
+        interrupt(cpu, mem, 0xFFFE);
+        return;
+    }
+
     // println!("pc: {:04X}", cpu.reg_pc);
 
-    let pc = cpu.reg_pc;
     let code = fetch_pc_byte(cpu, mem);
     let op = &opcode::OPCODE_TABLE[code as usize];
 
     // println!("{:04X}  {}                       A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}", pc, opcode::OPCODE_DEBUG_SYMBOL[code as usize], cpu.reg_a, cpu.reg_x, cpu.reg_y, cpu.reg_p, cpu.reg_s);
-    console::log_1(&format!("{:04X} {:02X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}", pc, code, cpu.reg_a, cpu.reg_x, cpu.reg_y, cpu.reg_p, cpu.reg_s).into());
 
     // opcode::debug_opcode(code);
     exec_instructions(cpu, mem, op);
     cpu.cycle = op.cycles as i16;
+    cpu.length = cpu.cycle;
+}
+
+// an instruction runs all its bus accesses at once, on the cycle it starts,
+// so that cycle stands in for the read at the end of the instruction
+pub fn is_reading(cpu: &Cpu) -> bool {
+    return cpu.cycle == cpu.length;
 }
 
 fn read_by_addressing(cpu: &mut Cpu, mem: &mut vmem::Vmem, op: &opcode::Opcode) -> u16 {
@@ -191,7 +220,6 @@ fn read_by_addressing(cpu: &mut Cpu, mem: &mut vmem::Vmem, op: &opcode::Opcode)
 fn exec_instructions(cpu: &mut Cpu, mem: &mut vmem::Vmem, op: &opcode::Opcode) {
     let mut data = read_by_addressing(cpu, mem, op);
     let relative = (data as u8) as i8;
-    console::log_1(&format!("data {:04X}", data).into());
     match op.code {
         opcode::OPCODE_LDA => {
             if op.addressing != opcode::ADDRESSING_IMMEDIATE {
@@ -0,0 +1,179 @@
+// standard controllers on $4016 and $4017, a 4021 shift register each that
+// latches the buttons while the strobe is high and shifts them out one read at a time.
+// turbo buttons are pressed and released here by the emulated frame count, so
+// a replayed input log latches them exactly as they were recorded
+
+// button bits, in the order the controller shifts them out
+pub const BUTTON_A: u8 = 0x01;
+pub const BUTTON_B: u8 = 0x02;
+pub const BUTTON_SELECT: u8 = 0x04;
+pub const BUTTON_START: u8 = 0x08;
+pub const BUTTON_UP: u8 = 0x10;
+pub const BUTTON_DOWN: u8 = 0x20;
+pub const BUTTON_LEFT: u8 = 0x40;
+pub const BUTTON_RIGHT: u8 = 0x80;
+
+pub const PORTS: usize = 2;
+
+// data lines the controller port drives, the others keep what was last on the bus
+const PORT_MASK: u8 = 0x1F;
+
+pub const DEFAULT_TURBO_PERIOD: u8 = 4;
+pub const DEFAULT_TURBO_ON_FRAMES: u8 = 2;
+
+#[derive(Clone, Copy)]
+struct Controller {
+    // held down now, given by the host
+    buttons: u8,
+    // held down as turbo buttons
+    turbo: u8,
+    shift: u8,
+}
+
+pub struct Input {
+    ports: [Controller; PORTS],
+    strobe: bool,
+    // turbo buttons repeat every period frames and are pressed for the first on_frames of them
+    turbo_period: u8,
+    turbo_on_frames: u8,
+    turbo_frame: u8,
+}
+
+pub fn new_input() -> Input {
+    return Input {
+        ports: [Controller { buttons: 0, turbo: 0, shift: 0 }; PORTS],
+        strobe: false,
+        turbo_period: DEFAULT_TURBO_PERIOD,
+        turbo_on_frames: DEFAULT_TURBO_ON_FRAMES,
+        turbo_frame: 0,
+    };
+}
+
+// what the shift register loads, the held buttons and the turbo buttons in their pressed phase
+fn latch(input: &Input, port: usize) -> u8 {
+    let controller = &input.ports[port];
+    if input.turbo_frame < input.turbo_on_frames {
+        return controller.buttons | controller.turbo;
+    }
+    return controller.buttons;
+}
+
+pub fn set_buttons(input: &mut Input, port: usize, buttons: u8) {
+    input.ports[port].buttons = buttons;
+    if input.strobe {
+        input.ports[port].shift = latch(input, port);
+    }
+}
+
+pub fn set_turbo_buttons(input: &mut Input, port: usize, buttons: u8) {
+    input.ports[port].turbo = buttons;
+    if input.strobe {
+        input.ports[port].shift = latch(input, port);
+    }
+}
+
+// period of at least 2 frames, pressed for 1 to period - 1 of them
+pub fn set_turbo_rate(input: &mut Input, period: u8, on_frames: u8) {
+    input.turbo_period = period.max(2);
+    input.turbo_on_frames = on_frames.max(1).min(input.turbo_period - 1);
+    input.turbo_frame = input.turbo_frame % input.turbo_period;
+}
+
+// advances the turbo phase, called once per frame
+pub fn clock_frame(input: &mut Input) {
+    input.turbo_frame = (input.turbo_frame + 1) % input.turbo_period;
+}
+
+// bit 0 of $4016 drives the strobe of both ports
+pub fn write_strobe(input: &mut Input, value: u8) {
+    input.strobe = (value & 0x01) != 0;
+    if input.strobe {
+        for port in 0..PORTS {
+            input.ports[port].shift = latch(input, port);
+        }
+    }
+}
+
+// the upper bits are open bus, usually $40 from the high byte of the address
+pub fn read_port(input: &mut Input, port: usize, open_bus: u8) -> u8 {
+    if input.strobe {
+        // the register keeps reloading, so every read returns A
+        input.ports[port].shift = latch(input, port);
+    }
+    let controller = &mut input.ports[port];
+    let bit = controller.shift & 0x01;
+    if !input.strobe {
+        // a standard controller shifts in 1s, reads after the eighth return 1
+        controller.shift = (controller.shift >> 1) | 0x80;
+    }
+    return (open_bus & !PORT_MASK) | bit;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the eight bits a program reads after strobing, A first
+    fn read_byte(input: &mut Input, port: usize) -> u8 {
+        let mut value = 0;
+        for bit in 0..8 {
+            value = value | ((read_port(input, port, 0x40) & 0x01) << bit);
+        }
+        return value;
+    }
+
+    fn strobe(input: &mut Input) {
+        write_strobe(input, 1);
+        write_strobe(input, 0);
+    }
+
+    #[test]
+    fn buttons_shift_out_in_order_then_ones() {
+        let mut input = new_input();
+        set_buttons(&mut input, 0, BUTTON_A | BUTTON_START | BUTTON_RIGHT);
+        set_buttons(&mut input, 1, BUTTON_B);
+        strobe(&mut input);
+        assert_eq!(read_byte(&mut input, 0), BUTTON_A | BUTTON_START | BUTTON_RIGHT);
+        assert_eq!(read_byte(&mut input, 1), BUTTON_B);
+        assert_eq!(read_byte(&mut input, 0), 0xFF);
+        // the upper bits come from the open bus
+        assert_eq!(read_port(&mut input, 0, 0x40), 0x41);
+    }
+
+    #[test]
+    fn strobe_high_keeps_returning_a() {
+        let mut input = new_input();
+        set_buttons(&mut input, 0, BUTTON_A | BUTTON_B);
+        write_strobe(&mut input, 1);
+        assert_eq!(read_byte(&mut input, 0), 0xFF);
+        set_buttons(&mut input, 0, BUTTON_B);
+        assert_eq!(read_byte(&mut input, 0), 0x00);
+        // buttons set after the strobe falls wait for the next strobe
+        write_strobe(&mut input, 0);
+        set_buttons(&mut input, 0, BUTTON_SELECT);
+        assert_eq!(read_byte(&mut input, 0), BUTTON_B);
+    }
+
+    #[test]
+    fn turbo_buttons_follow_the_frame_count() {
+        let mut input = new_input();
+        set_turbo_rate(&mut input, 3, 1);
+        set_turbo_buttons(&mut input, 0, BUTTON_A);
+        let mut pressed = Vec::new();
+        for _ in 0..6 {
+            strobe(&mut input);
+            pressed.push(read_byte(&mut input, 0));
+            clock_frame(&mut input);
+        }
+        assert_eq!(pressed, vec![BUTTON_A, 0, 0, BUTTON_A, 0, 0]);
+    }
+
+    #[test]
+    fn turbo_rate_is_clamped() {
+        let mut input = new_input();
+        set_turbo_rate(&mut input, 0, 5);
+        assert_eq!((input.turbo_period, input.turbo_on_frames), (2, 1));
+        set_turbo_rate(&mut input, 8, 0);
+        assert_eq!((input.turbo_period, input.turbo_on_frames), (8, 1));
+    }
+}
@@ -1,8 +1,42 @@
+use super::input;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExpansionChip {
+    Vrc6,
+    Vrc7,
+    Namco163,
+    Fds,
+    Mmc5,
+    Sunsoft5b,
+}
+
+// sound generated on the cartridge, mixed with the 2A03 output on a Famicom
+pub trait ExpansionAudio {
+    fn chip(&self) -> ExpansionChip;
+    // runs one cpu cycle
+    fn clock(&mut self);
+    fn channels(&self) -> usize;
+    // output of one channel, the sum of all channels at full volume is 1.0
+    fn output(&self, channel: usize) -> f32;
+    // registers of the chip in the cartridge space from $4020, none where it
+    // does not drive the data bus
+    fn read(&mut self, addr: u16) -> Option<u8>;
+    fn write(&mut self, addr: u16, value: u8);
+}
+
 pub struct Memory {
     pub wram: Vec<u8>,
     pub ext_ram: Vec<u8>,
     pub backup_ram: Vec<u8>,
     pub program_rom: Vec<u8>,
+    pub expansion_audio: Option<Box<dyn ExpansionAudio>>,
+    // cpu cycles left in the running oam dma
+    pub oam_dma_cycles: u16,
+    pub input: input::Input,
+    // last value on the cpu data bus, what unmapped and partly driven reads return
+    pub open_bus: u8,
+    // address of the last read, a dmc fetch that halts the cpu there repeats it
+    pub last_read: u16,
 }
 
 pub fn new_memory(rom_data: &Vec<u8>) -> Memory {
@@ -11,10 +45,30 @@ pub fn new_memory(rom_data: &Vec<u8>) -> Memory {
         ext_ram: vec![0; 0x1FE0],
         backup_ram: vec![0; 0x2000],
         program_rom: rom_data.clone(),
+        expansion_audio: None,
+        oam_dma_cycles: 0,
+        input: input::new_input(),
+        open_bus: 0,
+        last_read: 0,
     };
 }
 
+pub fn set_expansion_audio(mem: &mut Memory, source: Box<dyn ExpansionAudio>) {
+    mem.expansion_audio = Some(source);
+}
+
+pub fn run_expansion_audio(mem: &mut Memory) {
+    if let Some(source) = mem.expansion_audio.as_mut() {
+        source.clock();
+    }
+}
+
 pub fn read_mem(mem: &mut Memory, addr: u16) -> u8 {
+    if addr >= 0x4020 {
+        if let Some(value) = mem.expansion_audio.as_mut().and_then(|source| source.read(addr)) {
+            return value;
+        }
+    }
     let mut value = 0u8;
     if addr < 0x0800 {
         value = mem.wram[addr as usize];
@@ -49,6 +103,12 @@ pub fn read_mem(mem: &mut Memory, addr: u16) -> u8 {
 
 pub fn write_mem(mem: &mut Memory, addr: u16, value: u8) {
     // println!("write {:04X} value:{:02X}", addr, value);
+    if addr >= 0x4020 {
+        // the sound chip decodes its own registers, they may share addresses with ram or the mapper
+        if let Some(source) = mem.expansion_audio.as_mut() {
+            source.write(addr, value);
+        }
+    }
     if addr < 0x0800 {
         mem.wram[addr as usize] = value;
     } else if addr < 0x2000 {
@@ -67,4 +127,50 @@ pub fn write_mem(mem: &mut Memory, addr: u16, value: u8) {
         // program rom
         // read only
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a chip with one register at $9000 like the VRC6 pulse
+    struct Chip {
+        register: u8,
+    }
+
+    impl ExpansionAudio for Chip {
+        fn chip(&self) -> ExpansionChip {
+            return ExpansionChip::Mmc5;
+        }
+        fn clock(&mut self) {}
+        fn channels(&self) -> usize {
+            return 1;
+        }
+        fn output(&self, _channel: usize) -> f32 {
+            return 0.0;
+        }
+        fn read(&mut self, addr: u16) -> Option<u8> {
+            if addr == 0x9000 {
+                return Some(self.register);
+            }
+            return None;
+        }
+        fn write(&mut self, addr: u16, value: u8) {
+            if addr == 0x9000 {
+                self.register = value;
+            }
+        }
+    }
+
+    #[test]
+    fn cartridge_space_reaches_the_sound_chip() {
+        let mut mem = new_memory(&vec![0xEA; 0x8000]);
+        set_expansion_audio(&mut mem, Box::new(Chip { register: 0 }));
+        write_mem(&mut mem, 0x9000, 0x3C);
+        write_mem(&mut mem, 0x6000, 0x11);
+        assert_eq!(read_mem(&mut mem, 0x9000), 0x3C);
+        // addresses the chip does not drive read from the cartridge as before
+        assert_eq!(read_mem(&mut mem, 0x6000), 0x11);
+        assert_eq!(read_mem(&mut mem, 0x9001), 0xEA);
+    }
+}
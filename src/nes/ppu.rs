@@ -1,94 +1,316 @@
-use web_sys::console;
+use super::region;
 
-mod palette;
+pub mod palette;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenA,
+    SingleScreenB,
+    // the cartridge provides another 2 KiB so that all four nametables are distinct
+    FourScreen,
+}
 
 pub struct Ppu {
-    vram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_writable: bool,
+    ciram: Vec<u8>,
+    cartridge_vram: Vec<u8>,
+    palette_ram: Vec<u8>,
+    mirroring: Mirroring,
     oam: Vec<u8>,
-    offscreen_buffer: Vec<u8>,
-    vram_address: u16,
-    vram_write_counter: u8,
-    h_scroll: u8,
-    v_scroll: u8,
-    scroll_write_counter: u8,
+    // loopy registers: current and temporary vram address, fine x scroll and write toggle
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    w: u8,
     oam_address: u8,
     reg_controller: u8,
     reg_mask: u8,
     reg_status: u8,
     cycle: u32,
     rendering_status: u8,
+    // background fetch pipeline
+    nametable_latch: u8,
+    attribute_latch: u8,
+    pattern_low_latch: u8,
+    pattern_high_latch: u8,
+    bg_shift_pattern_low: u16,
+    bg_shift_pattern_high: u16,
+    bg_shift_attribute_low: u16,
+    bg_shift_attribute_high: u16,
+    // sprite pixels of the current scanline, see SPRITE_PIXEL_*
+    sprite_line: Vec<u8>,
+    sprite_limit: bool,
+    // $2007 reads return the value fetched by the previous read
+    read_buffer: u8,
+    // the data bus latch between the cpu and the ppu, every bit fades to 0 on its own
+    open_bus: u8,
+    open_bus_decay: Vec<u32>,
+    // page written to $4014, copied into oam by the bus
+    oam_dma_page: Option<u8>,
+    // the 2C07 swaps the red and green emphasis bits
+    swap_emphasis: bool,
+    // nmi is generated on the rising edge of vblank and ppuctrl bit 7
+    nmi_line: bool,
+    nmi_pending: bool,
+    // an nmi enabled by a $2000 write is taken after the next instruction
+    nmi_delay: bool,
+    // a $2002 read just before vblank starts keeps the flag from being set
+    suppress_vblank: bool,
+    odd_frame: bool,
+    region: region::Region,
+    // dot position of the start of vblank
+    vblank_start: u32,
+    prerender_line: u32,
+    frame_dots: u32,
+    // PAL runs 16 dots every 5 cpu cycles
+    dot_phase: u32,
+    // colour subcarrier phase in twelfths at the start of the frame, every dot is 8 twelfths long
+    colour_phase: u32,
 }
 
+// open bus bits fade out about 600ms after they were last driven
+const OPEN_BUS_DECAY_FRAMES: u32 = 36;
+
+// sprite_line layout: bits 0-1 colour, bits 2-3 palette
+const SPRITE_PIXEL_COLOUR: u8 = 0x03;
+const SPRITE_PIXEL_PALETTE: u8 = 0x0C;
+const SPRITE_PIXEL_BEHIND_BG: u8 = 0x20;
+const SPRITE_PIXEL_ZERO: u8 = 0x40;
+
+// hardware limit of sprites on one scanline
+const SPRITES_PER_LINE: usize = 8;
+
 pub fn new_ppu(rom_data: &Vec<u8>) -> Ppu {
-    let mut vram = vec![0; 0xFFFF];
-    for i in 0..rom_data.len() {
-        vram[i] = rom_data[i];
+    // boards without character rom have 8 KiB of character ram instead
+    let chr_writable = rom_data.len() == 0;
+    let mut chr = vec![0; 0x2000];
+    if !chr_writable {
+        chr = rom_data.clone();
     }
     return Ppu {
         cycle: 0,
         rendering_status: 0,
-        offscreen_buffer: vec![0; 256*240*4],
-        vram: vram,
+        chr: chr,
+        chr_writable: chr_writable,
+        ciram: vec![0; 0x0800],
+        cartridge_vram: vec![0; 0x0800],
+        palette_ram: vec![0; 0x20],
+        mirroring: Mirroring::Horizontal,
         oam: vec![0; 256],
-        vram_write_counter: 0,
-        scroll_write_counter: 0,
-        vram_address: 0,
-        h_scroll: 0,
-        v_scroll: 0,
+        v: 0,
+        t: 0,
+        fine_x: 0,
+        w: 0,
         oam_address: 0,
         reg_controller: 0,
         reg_mask: 0,
         reg_status: 0,
+        nametable_latch: 0,
+        attribute_latch: 0,
+        pattern_low_latch: 0,
+        pattern_high_latch: 0,
+        bg_shift_pattern_low: 0,
+        bg_shift_pattern_high: 0,
+        bg_shift_attribute_low: 0,
+        bg_shift_attribute_high: 0,
+        sprite_line: vec![0; 256],
+        sprite_limit: true,
+        read_buffer: 0,
+        open_bus: 0,
+        open_bus_decay: vec![0; 8],
+        oam_dma_page: None,
+        swap_emphasis: false,
+        nmi_line: false,
+        nmi_pending: false,
+        nmi_delay: false,
+        suppress_vblank: false,
+        odd_frame: false,
+        region: region::Region::Ntsc,
+        vblank_start: 241 * 341 + 1,
+        prerender_line: 261,
+        frame_dots: 262 * 341,
+        dot_phase: 0,
+        colour_phase: 0,
     };
 }
 
-pub fn read_io(ppu: &mut Ppu, addr: u16) -> u8 {
-    match addr {
-        0x2000 => {
-            // ppu controller
+pub fn set_mirroring(ppu: &mut Ppu, mirroring: Mirroring) {
+    ppu.mirroring = mirroring;
+}
+
+fn nametable_address(ppu: &Ppu, addr: u16) -> (bool, usize) {
+    let offset = (addr & 0x03FF) as usize;
+    let table = ((addr >> 10) & 0x03) as usize;
+    match ppu.mirroring {
+        Mirroring::Horizontal => (false, ((table >> 1) * 0x0400) + offset),
+        Mirroring::Vertical => (false, ((table & 1) * 0x0400) + offset),
+        Mirroring::SingleScreenA => (false, offset),
+        Mirroring::SingleScreenB => (false, 0x0400 + offset),
+        Mirroring::FourScreen => (table >= 2, ((table & 1) * 0x0400) + offset),
+    }
+}
+
+// $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
+fn palette_address(addr: u16) -> usize {
+    let index = (addr & 0x1F) as usize;
+    if (index & 0x13) == 0x10 {
+        return index & 0x0F;
+    }
+    return index;
+}
+
+pub fn read_vram(ppu: &Ppu, addr: u16) -> u8 {
+    let addr = addr & 0x3FFF;
+    if addr < 0x2000 {
+        // pattern tables
+        return ppu.chr[(addr as usize) % ppu.chr.len()];
+    } else if addr < 0x3F00 {
+        // nametables, $3000-$3EFF mirrors $2000-$2EFF
+        let (cartridge, index) = nametable_address(ppu, addr);
+        if cartridge {
+            return ppu.cartridge_vram[index];
         }
-        0x2001 => {
-            // ppu mask
+        return ppu.ciram[index];
+    }
+    // palettes
+    return ppu.palette_ram[palette_address(addr)];
+}
+
+pub fn write_vram(ppu: &mut Ppu, addr: u16, value: u8) {
+    let addr = addr & 0x3FFF;
+    if addr < 0x2000 {
+        // pattern tables
+        if ppu.chr_writable {
+            let index = (addr as usize) % ppu.chr.len();
+            ppu.chr[index] = value;
+        }
+    } else if addr < 0x3F00 {
+        // nametables
+        let (cartridge, index) = nametable_address(ppu, addr);
+        if cartridge {
+            ppu.cartridge_vram[index] = value;
+        } else {
+            ppu.ciram[index] = value;
+        }
+    } else {
+        // palettes
+        ppu.palette_ram[palette_address(addr)] = value & 0x3F;
+    }
+}
+
+// lifting the limit shows every sprite on a line to reduce flicker, the overflow flag still behaves as on hardware
+pub fn set_sprite_limit(ppu: &mut Ppu, enabled: bool) {
+    ppu.sprite_limit = enabled;
+}
+
+fn refresh_open_bus(ppu: &mut Ppu, value: u8, mask: u8) {
+    ppu.open_bus = (ppu.open_bus & !mask) | (value & mask);
+    for bit in 0..8 {
+        if (mask & (1 << bit)) != 0 {
+            ppu.open_bus_decay[bit] = OPEN_BUS_DECAY_FRAMES;
+        }
+    }
+}
+
+fn decay_open_bus(ppu: &mut Ppu) {
+    for bit in 0..8 {
+        if ppu.open_bus_decay[bit] > 0 {
+            ppu.open_bus_decay[bit] -= 1;
+            if ppu.open_bus_decay[bit] == 0 {
+                ppu.open_bus = ppu.open_bus & !(1 << bit);
+            }
         }
+    }
+}
+
+fn is_rendering_line(ppu: &Ppu) -> bool {
+    let scanline_y = ppu.cycle / 341;
+    return is_rendering_enabled(ppu) && (scanline_y < 240 || scanline_y == ppu.prerender_line);
+}
+
+fn increment_vram_address(ppu: &mut Ppu) {
+    if is_rendering_line(ppu) {
+        // while rendering the access bumps the scroll counters instead
+        increment_scroll_x(ppu);
+        increment_scroll_y(ppu);
+        return;
+    }
+    let increment = if (ppu.reg_controller & 0x04) != 0 { 32 } else { 1 };
+    ppu.v = ppu.v.wrapping_add(increment) & 0x7FFF;
+}
+
+pub fn read_io(ppu: &mut Ppu, addr: u16) -> u8 {
+    match addr {
         0x2002 => {
-            // ppu status
-            let status = ppu.reg_status;
+            // ppu status, the low 5 bits are open bus
+            if ppu.cycle == ppu.vblank_start {
+                // read one dot before the flag is set: it reads clear and never gets set
+                ppu.suppress_vblank = true;
+            }
+            let status = (ppu.reg_status & 0xE0) | (ppu.open_bus & 0x1F);
+            if ppu.cycle == ppu.vblank_start + 1 || ppu.cycle == ppu.vblank_start + 2 {
+                // read on the dots the flag is set: it reads set but the nmi is lost
+                ppu.nmi_pending = false;
+            }
             ppu.reg_status = ppu.reg_status & 0x7F;
-            ppu.scroll_write_counter = 0;
-            ppu.vram_write_counter = 0;
-            ppu.reg_status = ppu.reg_status | 0x80;
+            ppu.w = 0;
+            update_nmi(ppu);
+            refresh_open_bus(ppu, status, 0xE0);
             return status;
         }
-        0x2003 => {
-            // oam address
-        }
         0x2004 => {
-            // oam access
-        }
-        0x2005 => {
-            // scroll
-        }
-        0x2006 => {
-            // vram address
+            // oam access, bits 2-4 of the attribute byte do not exist
+            let mut value = ppu.oam[ppu.oam_address as usize];
+            if (ppu.oam_address & 0x03) == 0x02 {
+                value = value & 0xE3;
+            }
+            refresh_open_bus(ppu, value, 0xFF);
+            return value;
         }
         0x2007 => {
             // vram access
-            return ppu.vram[ppu.vram_address as usize];
-        }
-        0x4014 => {
-            // oam dma
+            let addr = ppu.v & 0x3FFF;
+            let value;
+            if addr < 0x3F00 {
+                value = ppu.read_buffer;
+                ppu.read_buffer = read_vram(ppu, addr);
+                refresh_open_bus(ppu, value, 0xFF);
+            } else {
+                // palettes are returned immediately, the buffer gets the nametable byte underneath
+                value = (read_vram(ppu, addr) & 0x3F) | (ppu.open_bus & 0xC0);
+                ppu.read_buffer = read_vram(ppu, addr - 0x1000);
+                refresh_open_bus(ppu, value, 0x3F);
+            }
+            increment_vram_address(ppu);
+            return value;
         }
         _ => {
+            // write only registers return the open bus
         }
     }
-    return 0;
+    return ppu.open_bus;
 }
 
 pub fn write_io(ppu: &mut Ppu, addr: u16, value: u8) {
+    if addr != 0x4014 {
+        refresh_open_bus(ppu, value, 0xFF);
+    }
     match addr {
         0x2000 => {
             // ppu controller
             ppu.reg_controller = value;
+            ppu.t = (ppu.t & 0xF3FF) | (((value & 0x03) as u16) << 10);
+            if (value & 0x80) == 0 && (ppu.cycle == ppu.vblank_start + 1 || ppu.cycle == ppu.vblank_start + 2) {
+                // disabling nmi right as vblank starts cancels it
+                ppu.nmi_pending = false;
+            }
+            if !ppu.nmi_line && (value & 0x80) != 0 && (ppu.reg_status & 0x80) != 0 {
+                ppu.nmi_delay = true;
+            }
+            update_nmi(ppu);
         }
         0x2001 => {
             // ppu mask
@@ -105,49 +327,76 @@ pub fn write_io(ppu: &mut Ppu, addr: u16, value: u8) {
             // oam access
             ppu.oam[ppu.oam_address as usize] = value;
             // println!("oam address {:04X} = {:02X}", ppu.oam_address, value);
+            ppu.oam_address = ppu.oam_address.wrapping_add(1);
         }
         0x2005 => {
             // scroll
-            if ppu.scroll_write_counter == 0 {
-                ppu.h_scroll = value;
+            if ppu.w == 0 {
+                ppu.t = (ppu.t & 0xFFE0) | ((value >> 3) as u16);
+                ppu.fine_x = value & 0x07;
             } else {
-                ppu.v_scroll = value;
+                ppu.t = (ppu.t & 0x8C1F) | (((value & 0x07) as u16) << 12) | (((value & 0xF8) as u16) << 2);
             }
-            ppu.scroll_write_counter = ppu.scroll_write_counter ^ 1;
+            ppu.w = ppu.w ^ 1;
         }
         0x2006 => {
             // vram address
-            let shift: u8 = (1 - ppu.vram_write_counter) * 8;
-            if ppu.vram_write_counter == 0 {
-                ppu.vram_address = 0;
+            if ppu.w == 0 {
+                ppu.t = (ppu.t & 0x80FF) | (((value & 0x3F) as u16) << 8);
+            } else {
+                ppu.t = (ppu.t & 0xFF00) | (value as u16);
+                ppu.v = ppu.t;
             }
-            ppu.vram_address = ppu.vram_address | ((value as u16) << shift);
-            ppu.vram_write_counter = ppu.vram_write_counter ^ 1;
+            ppu.w = ppu.w ^ 1;
         }
         0x2007 => {
             // vram access
-            ppu.vram[ppu.vram_address as usize] = value;
-            // println!("vram address {:04X} = {:02X}", ppu.vram_address, value);
-            ppu.vram_address += 1;
+            write_vram(ppu, ppu.v, value);
+            // println!("vram address {:04X} = {:02X}", ppu.v, value);
+            increment_vram_address(ppu);
         }
         0x4014 => {
-            // oam dma
-            println!("oam dma");
+            // oam dma, the ppu cannot see cpu memory so the bus does the copy
+            ppu.oam_dma_page = Some(value);
         }
         _ => {
         }
     }
 }
 
-const ADDR_PATTERN0: u16 = 0x0000;
-const ADDR_PATTERN1: u16 = 0x1000;
-const ADDR_BG0: u16 = 0x2000;
-const ADDR_BG1: u16 = 0x2400;
-const ADDR_BG2: u16 = 0x2800;
-const ADDR_BG3: u16 = 0x2C00;
+fn update_nmi(ppu: &mut Ppu) {
+    let line = (ppu.reg_status & 0x80) != 0 && (ppu.reg_controller & 0x80) != 0;
+    if line && !ppu.nmi_line {
+        ppu.nmi_pending = true;
+    }
+    ppu.nmi_line = line;
+}
+
+// polled by the cpu between instructions
+pub fn take_nmi(ppu: &mut Ppu) -> bool {
+    if !ppu.nmi_pending {
+        ppu.nmi_delay = false;
+        return false;
+    }
+    if ppu.nmi_delay {
+        ppu.nmi_delay = false;
+        return false;
+    }
+    ppu.nmi_pending = false;
+    return true;
+}
+
+// the ntsc filter uses it to make the artifacts crawl like on a TV
+pub fn colour_phase(ppu: &Ppu) -> u32 {
+    return ppu.colour_phase;
+}
+
+pub fn oam_dma_request(ppu: &mut Ppu) -> Option<u8> {
+    return ppu.oam_dma_page.take();
+}
 
 #[inline(always)]
-fn put_pixel(buffer: &mut Vec<u8>, x: i32, y: i32, r: u8, g: u8, b: u8) {
+fn put_pixel(buffer: &mut Vec<u16>, x: i32, y: i32, colour: u16) {
     if 0 > x || x >= 256 {
         return;
     }
@@ -155,45 +404,56 @@ fn put_pixel(buffer: &mut Vec<u8>, x: i32, y: i32, r: u8, g: u8, b: u8) {
         return;
     }
 
-    let offset = ((y*256 + x) * 4) as usize;
-    buffer[offset] = r;
-    buffer[offset + 1] = g;
-    buffer[offset + 2] = b;
-    buffer[offset + 3] = 0xFF;
+    buffer[(y*256 + x) as usize] = colour;
 }
 
+// offset selects one of the four background (0x00-0x0C) or sprite (0x10-0x1C) palettes,
+// colour 0 of every palette shows the universal backdrop colour
 #[inline(always)]
-fn get_palette(ppu: &mut Ppu, palette_num: u8, offset: u8) -> (u8, u8, u8) {
-    let mut address = (0x3F00 + (offset as u16) + (palette_num as u16)) as usize;
+fn get_palette(ppu: &mut Ppu, palette_num: u8, offset: u8) -> u16 {
+    let mut address = 0x3F00 + (offset as u16) + (palette_num as u16);
     if palette_num == 0 {
         address = 0x3F00;
     }
-    let palette_color = ((ppu.vram[address] & 0x3F) * 3) as usize;
-    return (palette::PALETTE_TABLE[palette_color], palette::PALETTE_TABLE[palette_color + 1], palette::PALETTE_TABLE[palette_color + 2]);
+    return (read_vram(ppu, address) & 0x3F) as u16;
 }
 
-#[inline(always)]
-fn put_tile(ppu: &mut Ppu, base_x: i32, base_y: i32, base_addr: u16, chrnum: u8, palette_offset: u8) {
-    for y in 0..8 {
-        let addr = base_addr + ((chrnum as u16) * 16);
-        let palette_data_low = ppu.vram[(addr + 0 + y) as usize];
-        let palette_data_high = ppu.vram[(addr + 8 + y) as usize];
-        for x in 0..8 {
-            let palette_num = (
-                 ((palette_data_low  >> (7 - x)) & 1) |
-                (((palette_data_high >> (7 - x)) & 1) << 1)
-            ) as u8;
-            let (r, g, b) = get_palette(ppu, palette_num, palette_offset);
-            put_pixel(&mut ppu.offscreen_buffer, base_x + x, base_y + (y as i32), r, g, b);
-        }
+// applies greyscale (bit 0) and colour emphasis (bits 5-7) of ppumask,
+// the result is a 9 bit index: 6 bit colour and red, green, blue emphasis
+fn apply_mask(ppu: &Ppu, colour: u16) -> u16 {
+    let mut colour = colour;
+    if (ppu.reg_mask & 0x01) != 0 {
+        colour = colour & 0x30;
+    }
+    let mut emphasis = ((ppu.reg_mask >> 5) & 0x07) as u16;
+    if ppu.swap_emphasis {
+        emphasis = (emphasis & 0x04) | ((emphasis & 0x01) << 1) | ((emphasis & 0x02) >> 1);
     }
+    return colour | (emphasis << 6);
 }
 
-#[inline(always)]
-fn put_bg_tile(ppu: &mut Ppu, base_x: i32, base_y: i32, chrnum: u8) {
-    let base_addr = (((ppu.reg_controller >> 4) & 1) as u16) * 0x1000;
-    // println!("put bg x:{} y:{} addr:{:04X}", base_x, base_y, base_addr);
-    put_tile(ppu, base_x, base_y, base_addr, chrnum, 0x00);
+fn is_bg_visible(ppu: &Ppu, x: u32) -> bool {
+    return (ppu.reg_mask & 0x08) != 0 && (x >= 8 || (ppu.reg_mask & 0x02) != 0);
+}
+
+fn is_sprite_visible(ppu: &Ppu, x: u32) -> bool {
+    return (ppu.reg_mask & 0x10) != 0 && (x >= 8 || (ppu.reg_mask & 0x04) != 0);
+}
+
+// PAL and Dendy have 312 lines, Dendy starts vblank 51 lines after the picture instead of 1
+pub fn set_region(ppu: &mut Ppu, region: region::Region) {
+    ppu.region = region;
+    let (scanlines, vblank_line) = match region {
+        region::Region::Ntsc => (262, 241),
+        region::Region::Pal => (312, 241),
+        region::Region::Dendy => (312, 291),
+    };
+    ppu.vblank_start = vblank_line * 341 + 1;
+    ppu.prerender_line = scanlines - 1;
+    ppu.frame_dots = scanlines * 341;
+    ppu.swap_emphasis = region != region::Region::Ntsc;
+    ppu.cycle = 0;
+    ppu.dot_phase = 0;
 }
 
 pub fn is_draw_timing(ppu: &Ppu) -> bool {
@@ -204,61 +464,825 @@ pub fn check_drawn(ppu: &mut Ppu) {
     ppu.rendering_status = 2;
 }
 
-pub fn draw_bg(ppu: &mut Ppu) {
-    // console::log_1(&format!("ppu controller:{:02X} mask:{:02X} status:{:02X}", ppu.reg_controller, ppu.reg_mask, ppu.reg_status).into());
-    for y in 0..(240/8) {
-        for x in 0..(256/8) {
-            let bgaddr = ADDR_BG0 + y * 32 + x;
-            let chrnum = ppu.vram[bgaddr as usize];
-            put_bg_tile(ppu, (x * 8) as i32, (y * 8) as i32, chrnum);
+fn is_rendering_enabled(ppu: &Ppu) -> bool {
+    return (ppu.reg_mask & 0x18) != 0;
+}
+
+fn increment_scroll_x(ppu: &mut Ppu) {
+    if (ppu.v & 0x001F) == 31 {
+        // wrap coarse x and switch horizontal nametable
+        ppu.v = (ppu.v & !0x001F) ^ 0x0400;
+    } else {
+        ppu.v += 1;
+    }
+}
+
+fn increment_scroll_y(ppu: &mut Ppu) {
+    if (ppu.v & 0x7000) != 0x7000 {
+        ppu.v += 0x1000;
+        return;
+    }
+    // fine y overflows into coarse y
+    ppu.v = ppu.v & !0x7000;
+    let mut coarse_y = (ppu.v & 0x03E0) >> 5;
+    if coarse_y == 29 {
+        coarse_y = 0;
+        // switch vertical nametable
+        ppu.v = ppu.v ^ 0x0800;
+    } else if coarse_y == 31 {
+        coarse_y = 0;
+    } else {
+        coarse_y += 1;
+    }
+    ppu.v = (ppu.v & !0x03E0) | (coarse_y << 5);
+}
+
+fn copy_horizontal_bits(ppu: &mut Ppu) {
+    ppu.v = (ppu.v & !0x041F) | (ppu.t & 0x041F);
+}
+
+fn copy_vertical_bits(ppu: &mut Ppu) {
+    ppu.v = (ppu.v & !0x7BE0) | (ppu.t & 0x7BE0);
+}
+
+fn load_bg_shifters(ppu: &mut Ppu) {
+    ppu.bg_shift_pattern_low = (ppu.bg_shift_pattern_low & 0xFF00) | (ppu.pattern_low_latch as u16);
+    ppu.bg_shift_pattern_high = (ppu.bg_shift_pattern_high & 0xFF00) | (ppu.pattern_high_latch as u16);
+    ppu.bg_shift_attribute_low = (ppu.bg_shift_attribute_low & 0xFF00) | (if (ppu.attribute_latch & 1) != 0 { 0xFF } else { 0x00 });
+    ppu.bg_shift_attribute_high = (ppu.bg_shift_attribute_high & 0xFF00) | (if (ppu.attribute_latch & 2) != 0 { 0xFF } else { 0x00 });
+}
+
+fn shift_bg_shifters(ppu: &mut Ppu) {
+    ppu.bg_shift_pattern_low = ppu.bg_shift_pattern_low << 1;
+    ppu.bg_shift_pattern_high = ppu.bg_shift_pattern_high << 1;
+    ppu.bg_shift_attribute_low = ppu.bg_shift_attribute_low << 1;
+    ppu.bg_shift_attribute_high = ppu.bg_shift_attribute_high << 1;
+}
+
+fn fetch_bg(ppu: &mut Ppu, step: u32) {
+    match step {
+        0 => {
+            load_bg_shifters(ppu);
+            ppu.nametable_latch = read_vram(ppu, 0x2000 | (ppu.v & 0x0FFF));
+        }
+        2 => {
+            let addr = 0x23C0 | (ppu.v & 0x0C00) | ((ppu.v >> 4) & 0x38) | ((ppu.v >> 2) & 0x07);
+            let mut attribute = read_vram(ppu, addr);
+            // pick the 2 bits of the 16x16 quadrant the tile belongs to
+            if (ppu.v & 0x0040) != 0 {
+                attribute = attribute >> 4;
+            }
+            if (ppu.v & 0x0002) != 0 {
+                attribute = attribute >> 2;
+            }
+            ppu.attribute_latch = attribute & 0x03;
+        }
+        4 => {
+            let addr = (((ppu.reg_controller >> 4) & 1) as u16) * 0x1000 + (ppu.nametable_latch as u16) * 16 + ((ppu.v >> 12) & 0x07);
+            ppu.pattern_low_latch = read_vram(ppu, addr);
+        }
+        6 => {
+            let addr = (((ppu.reg_controller >> 4) & 1) as u16) * 0x1000 + (ppu.nametable_latch as u16) * 16 + ((ppu.v >> 12) & 0x07) + 8;
+            ppu.pattern_high_latch = read_vram(ppu, addr);
+        }
+        7 => {
+            increment_scroll_x(ppu);
+        }
+        _ => {
         }
     }
 }
 
-pub fn draw_oam(ppu: &mut Ppu) {
-    for i in 0..(256/4) {
+// sprite 0 hits where its opaque pixel overlaps an opaque background pixel, except at
+// x=255 and in the left 8 pixels while either layer is clipped there
+fn is_sprite_zero_hit(ppu: &Ppu, x: u32, bg_pixel: u8, sprite_pixel: u8) -> bool {
+    if (sprite_pixel & SPRITE_PIXEL_ZERO) == 0 || (sprite_pixel & SPRITE_PIXEL_COLOUR) == 0 || bg_pixel == 0 {
+        return false;
+    }
+    if (ppu.reg_mask & 0x18) != 0x18 || x == 255 {
+        return false;
+    }
+    if x < 8 && (ppu.reg_mask & 0x06) != 0x06 {
+        return false;
+    }
+    return true;
+}
+
+fn sprite_height(ppu: &Ppu) -> u32 {
+    return if (ppu.reg_controller & 0x20) != 0 { 16 } else { 8 };
+}
+
+fn is_sprite_in_range(ppu: &Ppu, sprite_y: u8, scanline_y: u32) -> bool {
+    let y = sprite_y as u32;
+    return scanline_y >= y && scanline_y < y + sprite_height(ppu);
+}
+
+// finds the sprites on the line after scanline_y the way the hardware fills secondary oam,
+// including the diagonal oam scan that makes the overflow flag unreliable
+fn find_sprites(ppu: &mut Ppu, scanline_y: u32) -> Vec<usize> {
+    let mut sprites = Vec::new();
+    let mut n = 0;
+    while n < 64 && sprites.len() < SPRITES_PER_LINE {
+        if is_sprite_in_range(ppu, ppu.oam[n * 4], scanline_y) {
+            sprites.push(n);
+        }
+        n += 1;
+    }
+
+    let mut m = 0;
+    while n < 64 {
+        if is_sprite_in_range(ppu, ppu.oam[n * 4 + m], scanline_y) {
+            ppu.reg_status = ppu.reg_status | 0x20;
+            break;
+        }
+        // the hardware increments both the sprite and the byte index here
+        n += 1;
+        m = (m + 1) & 3;
+    }
+
+    if !ppu.sprite_limit {
+        for i in (sprites.last().map_or(0, |&last| last + 1))..64 {
+            if is_sprite_in_range(ppu, ppu.oam[i * 4], scanline_y) {
+                sprites.push(i);
+            }
+        }
+    }
+    return sprites;
+}
+
+// sprite y is one less than the first line the sprite appears on, so the
+// sprites of line scanline_y + 1 are evaluated and fetched during scanline_y
+fn evaluate_sprites(ppu: &mut Ppu, scanline_y: u32) {
+    for x in 0..256 {
+        ppu.sprite_line[x] = 0;
+    }
+
+    let height = sprite_height(ppu);
+    for i in find_sprites(ppu, scanline_y) {
         let base = i * 4;
-        let y = ppu.oam[base + 0];
+        let y = ppu.oam[base + 0] as u32;
         let tile = ppu.oam[base + 1];
         let attr = ppu.oam[base + 2];
-        let x = ppu.oam[base + 3];
+        let x = ppu.oam[base + 3] as usize;
+
+        let mut row = (scanline_y - y) as u16;
+        if (attr & 0x80) != 0 {
+            // vertical flip
+            row = (height as u16) - 1 - row;
+        }
+        let addr = if height == 16 {
+            // 8x16 sprites pick the pattern table with bit 0 of the tile index
+            let bank = ((tile & 1) as u16) * 0x1000;
+            bank + (((tile & 0xFE) as u16) + (row / 8)) * 16 + (row % 8)
+        } else {
+            let bank = (((ppu.reg_controller >> 3) & 1) as u16) * 0x1000;
+            bank + (tile as u16) * 16 + row
+        };
+        let pattern_low = read_vram(ppu, addr);
+        let pattern_high = read_vram(ppu, addr + 8);
 
-        let base_addr = (((ppu.reg_controller >> 3) & 1) as u16) * 0x1000;
-        // console::log_1(&format!("put bg x:{} y:{} addr:{:04X}", x, y, base_addr).into());
-        put_tile(ppu, x as i32, y as i32, base_addr, tile, 0x10);
+        for px in 0..8 {
+            // horizontal flip
+            let shift = if (attr & 0x40) != 0 { px } else { 7 - px };
+            let colour = ((pattern_low >> shift) & 1) | (((pattern_high >> shift) & 1) << 1);
+            // the first opaque sprite pixel wins, even when it is behind the background
+            if colour == 0 || x + px >= 256 || (ppu.sprite_line[x + px] & SPRITE_PIXEL_COLOUR) != 0 {
+                continue;
+            }
+            ppu.sprite_line[x + px] = colour | ((attr & 0x03) << 2) | (if (attr & 0x20) != 0 { SPRITE_PIXEL_BEHIND_BG } else { 0 }) | (if i == 0 { SPRITE_PIXEL_ZERO } else { 0 });
+        }
     }
 }
 
-pub fn run(canvas: &mut Vec<u8>, ppu: &mut Ppu) {
-    for _ in 0..3 {
-        let scanline_x = ppu.cycle % 262;
-        let scanline_y = ppu.cycle / 262;
+fn render_dot(canvas: &mut Vec<u16>, ppu: &mut Ppu, scanline_x: u32, scanline_y: u32) {
+    let visible_line = scanline_y < 240;
+    let prerender_line = scanline_y == ppu.prerender_line;
+    if !visible_line && !prerender_line {
+        return;
+    }
 
-        if scanline_x == 0 && scanline_y == 0 {
-            draw_bg(ppu);
-            draw_oam(ppu);
+    if is_rendering_enabled(ppu) {
+        if (scanline_x >= 2 && scanline_x < 258) || (scanline_x >= 321 && scanline_x < 338) {
+            shift_bg_shifters(ppu);
+            fetch_bg(ppu, (scanline_x - 1) % 8);
         }
-
-        if scanline_y >= 261 {
-            ppu.reg_status = ppu.reg_status | 0x80;
-            ppu.rendering_status = 0;
-        } else if scanline_y >= 241 {
-            ppu.reg_status = ppu.reg_status & 0x7F;
-            if ppu.rendering_status == 0 {
-                ppu.rendering_status = 1;
+        if scanline_x == 256 {
+            increment_scroll_y(ppu);
+        }
+        if scanline_x == 257 {
+            load_bg_shifters(ppu);
+            copy_horizontal_bits(ppu);
+            if visible_line {
+                evaluate_sprites(ppu, scanline_y);
+            } else {
+                // no sprites on the first line
+                for x in 0..256 {
+                    ppu.sprite_line[x] = 0;
+                }
             }
         }
+        if prerender_line && scanline_x >= 280 && scanline_x < 305 {
+            copy_vertical_bits(ppu);
+        }
+    }
+
+    if visible_line && scanline_x >= 1 && scanline_x <= 256 {
+        let x = scanline_x - 1;
+        let mux = 0x8000 >> ppu.fine_x;
+        let mut bg_pixel = (if (ppu.bg_shift_pattern_low & mux) != 0 { 1 } else { 0 }) | (if (ppu.bg_shift_pattern_high & mux) != 0 { 2 } else { 0 });
+        let bg_palette = (if (ppu.bg_shift_attribute_low & mux) != 0 { 1 } else { 0 }) | (if (ppu.bg_shift_attribute_high & mux) != 0 { 2 } else { 0 });
+        let mut sprite_pixel = ppu.sprite_line[x as usize];
+        if !is_bg_visible(ppu, x) {
+            bg_pixel = 0;
+        }
+        if !is_sprite_visible(ppu, x) {
+            sprite_pixel = 0;
+        }
+        let sprite_colour = sprite_pixel & SPRITE_PIXEL_COLOUR;
+
+        if is_sprite_zero_hit(ppu, x, bg_pixel, sprite_pixel) {
+            ppu.reg_status = ppu.reg_status | 0x40;
+        }
+
+        let sprite_in_front = sprite_colour != 0 && (bg_pixel == 0 || (sprite_pixel & SPRITE_PIXEL_BEHIND_BG) == 0);
+        let colour = if sprite_in_front {
+            get_palette(ppu, sprite_colour, 0x10 | (sprite_pixel & SPRITE_PIXEL_PALETTE))
+        } else if !is_rendering_enabled(ppu) && (ppu.v & 0x3F00) == 0x3F00 {
+            // with rendering off the backdrop comes from the palette entry v points at
+            (read_vram(ppu, ppu.v) & 0x3F) as u16
+        } else {
+            get_palette(ppu, bg_pixel, bg_palette * 4)
+        };
+        put_pixel(canvas, x as i32, scanline_y as i32, apply_mask(ppu, colour));
+    }
+}
+
+// canvas receives 256x240 9 bit colour indices, see palette::to_rgba
+pub fn run(canvas: &mut Vec<u16>, ppu: &mut Ppu) {
+    let mut dots = 3;
+    if ppu.region == region::Region::Pal {
+        ppu.dot_phase = (ppu.dot_phase + 1) % 5;
+        if ppu.dot_phase == 0 {
+            dots = 4;
+        }
+    }
+    for _ in 0..dots {
+        let scanline_x = ppu.cycle % 341;
+        let scanline_y = ppu.cycle / 341;
+
+        render_dot(canvas, ppu, scanline_x, scanline_y);
 
-        if scanline_x < 256 && scanline_y < 240 {
-            let offset = ((scanline_y*256 + scanline_x) * 4) as usize;
-            for i in 0..4 {
-                canvas[offset + i] = ppu.offscreen_buffer[offset + i];
+        if ppu.cycle == ppu.vblank_start {
+            if !ppu.suppress_vblank {
+                ppu.reg_status = ppu.reg_status | 0x80;
             }
+            ppu.suppress_vblank = false;
+            ppu.rendering_status = 1;
+            decay_open_bus(ppu);
+        } else if ppu.cycle == ppu.prerender_line * 341 + 1 {
+            // vblank, sprite 0 hit and sprite overflow
+            ppu.reg_status = ppu.reg_status & 0x1F;
+            ppu.rendering_status = 0;
         }
+        update_nmi(ppu);
 
         ppu.cycle = ppu.cycle + 1;
-        if ppu.cycle >= 341*262 {
+        if ppu.cycle == ppu.frame_dots - 1 && ppu.odd_frame && is_rendering_enabled(ppu) && ppu.region == region::Region::Ntsc {
+            // odd frames skip the last dot of the pre-render line
+            ppu.cycle = ppu.frame_dots;
+            ppu.colour_phase = (ppu.colour_phase + 12 - 8) % 12;
+        }
+        if ppu.cycle >= ppu.frame_dots {
             ppu.cycle = 0;
+            ppu.colour_phase = (ppu.colour_phase + ppu.frame_dots * 8) % 12;
+            ppu.odd_frame = !ppu.odd_frame;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tile 1 is solid colour 1, tile 2 only has its rightmost column opaque
+    fn test_ppu(background_tile: u8) -> Ppu {
+        let mut chr = vec![0; 0x2000];
+        for row in 0..8 {
+            chr[16 + row] = 0xFF;
+            chr[32 + row] = 0x01;
+        }
+        let mut ppu = new_ppu(&chr);
+        for offset in 0..960 {
+            write_vram(&mut ppu, 0x2000 + offset, background_tile);
+        }
+        return ppu;
+    }
+
+    fn set_sprite_zero(ppu: &mut Ppu, y: u8, tile: u8, attr: u8, x: u8) {
+        ppu.oam[0..4].copy_from_slice(&[y, tile, attr, x]);
+        for byte in ppu.oam[4..].iter_mut() {
+            *byte = 0xFF;
+        }
+    }
+
+    // renders from the pre-render line to vblank and returns whether sprite 0 hit
+    fn sprite_zero_hits(ppu: &mut Ppu, mask: u8) -> bool {
+        let mut canvas = vec![0; 256 * 240];
+        write_io(ppu, 0x2001, mask);
+        ppu.cycle = ppu.prerender_line * 341;
+        run(&mut canvas, ppu);
+        while (ppu.reg_status & 0x80) == 0 {
+            run(&mut canvas, ppu);
+        }
+        return (ppu.reg_status & 0x40) != 0;
+    }
+
+    #[test]
+    fn sprite_zero_hits_on_opaque_background() {
+        let mut ppu = test_ppu(1);
+        set_sprite_zero(&mut ppu, 100, 1, 0x00, 100);
+        assert!(sprite_zero_hits(&mut ppu, 0x1E));
+        // behind the background still hits
+        set_sprite_zero(&mut ppu, 100, 1, 0x20, 100);
+        assert!(sprite_zero_hits(&mut ppu, 0x1E));
+        // over a transparent background or with a layer off it does not
+        let mut ppu = test_ppu(0);
+        set_sprite_zero(&mut ppu, 100, 1, 0x00, 100);
+        assert!(!sprite_zero_hits(&mut ppu, 0x1E));
+        let mut ppu = test_ppu(1);
+        set_sprite_zero(&mut ppu, 100, 1, 0x00, 100);
+        assert!(!sprite_zero_hits(&mut ppu, 0x0E));
+        assert!(!sprite_zero_hits(&mut ppu, 0x16));
+    }
+
+    #[test]
+    fn sprite_zero_misses_at_the_edges() {
+        let mut ppu = test_ppu(1);
+        // only pixel x=255 is opaque
+        set_sprite_zero(&mut ppu, 100, 2, 0x00, 248);
+        assert!(!sprite_zero_hits(&mut ppu, 0x1E));
+        // flipped the opaque column lands on x=248
+        set_sprite_zero(&mut ppu, 100, 2, 0x40, 248);
+        assert!(sprite_zero_hits(&mut ppu, 0x1E));
+        // the left 8 pixels only hit with both layers shown there
+        set_sprite_zero(&mut ppu, 100, 2, 0x40, 0);
+        assert!(sprite_zero_hits(&mut ppu, 0x1E));
+        assert!(!sprite_zero_hits(&mut ppu, 0x1C));
+        assert!(!sprite_zero_hits(&mut ppu, 0x1A));
+        // y of 239 and below the screen is never drawn
+        set_sprite_zero(&mut ppu, 239, 1, 0x00, 100);
+        assert!(!sprite_zero_hits(&mut ppu, 0x1E));
+    }
+
+    #[test]
+    fn sprite_zero_flag_clears_on_the_pre_render_line() {
+        let mut ppu = test_ppu(1);
+        set_sprite_zero(&mut ppu, 100, 1, 0x00, 100);
+        assert!(sprite_zero_hits(&mut ppu, 0x1E));
+        let mut canvas = vec![0; 256 * 240];
+        ppu.cycle = ppu.prerender_line * 341;
+        run(&mut canvas, &mut ppu);
+        assert_eq!(ppu.reg_status & 0xC0, 0);
+    }
+
+    // puts the ppu so that the next cpu cycle runs the 3 dots from dot on
+    fn at_dot(ppu: &mut Ppu, dot: u32) {
+        ppu.cycle = dot;
+    }
+
+    fn run_cpu_cycle(ppu: &mut Ppu) {
+        let mut canvas = vec![0; 256 * 240];
+        run(&mut canvas, ppu);
+    }
+
+    #[test]
+    fn status_read_just_before_vblank_suppresses_flag_and_nmi() {
+        let mut ppu = test_ppu(0);
+        write_io(&mut ppu, 0x2000, 0x80);
+        let start = ppu.vblank_start;
+        at_dot(&mut ppu, start);
+        assert_eq!(read_io(&mut ppu, 0x2002) & 0x80, 0);
+        run_cpu_cycle(&mut ppu);
+        assert_eq!(ppu.reg_status & 0x80, 0);
+        assert!(!take_nmi(&mut ppu));
+    }
+
+    #[test]
+    fn status_read_as_vblank_starts_reads_set_and_loses_the_nmi() {
+        let mut ppu = test_ppu(0);
+        write_io(&mut ppu, 0x2000, 0x80);
+        let start = ppu.vblank_start;
+        at_dot(&mut ppu, start - 2);
+        run_cpu_cycle(&mut ppu);
+        assert_eq!(ppu.cycle, start + 1);
+        assert_eq!(read_io(&mut ppu, 0x2002) & 0x80, 0x80);
+        assert!(!take_nmi(&mut ppu));
+        // the read cleared the flag
+        assert_eq!(read_io(&mut ppu, 0x2002) & 0x80, 0);
+    }
+
+    #[test]
+    fn status_read_later_in_vblank_keeps_the_nmi() {
+        let mut ppu = test_ppu(0);
+        write_io(&mut ppu, 0x2000, 0x80);
+        let start = ppu.vblank_start;
+        at_dot(&mut ppu, start - 2);
+        run_cpu_cycle(&mut ppu);
+        run_cpu_cycle(&mut ppu);
+        assert_eq!(read_io(&mut ppu, 0x2002) & 0x80, 0x80);
+        assert!(take_nmi(&mut ppu));
+        assert!(!take_nmi(&mut ppu));
+    }
+
+    #[test]
+    fn enabling_nmi_in_vblank_fires_after_the_next_instruction() {
+        let mut ppu = test_ppu(0);
+        let start = ppu.vblank_start;
+        at_dot(&mut ppu, start - 2);
+        run_cpu_cycle(&mut ppu);
+        assert!(!take_nmi(&mut ppu));
+        write_io(&mut ppu, 0x2000, 0x80);
+        assert!(!take_nmi(&mut ppu));
+        assert!(take_nmi(&mut ppu));
+        // disabling and enabling again while the flag is set makes another edge
+        write_io(&mut ppu, 0x2000, 0x00);
+        write_io(&mut ppu, 0x2000, 0x80);
+        assert!(!take_nmi(&mut ppu));
+        assert!(take_nmi(&mut ppu));
+    }
+
+    #[test]
+    fn odd_frames_skip_a_dot_while_rendering() {
+        let mut ppu = test_ppu(0);
+        let end = ppu.frame_dots;
+        at_dot(&mut ppu, end - 3);
+        run_cpu_cycle(&mut ppu);
+        assert_eq!((ppu.cycle, ppu.odd_frame), (0, true));
+        write_io(&mut ppu, 0x2001, 0x08);
+        at_dot(&mut ppu, end - 3);
+        run_cpu_cycle(&mut ppu);
+        assert_eq!((ppu.cycle, ppu.odd_frame), (1, false));
+        // even frames run every dot
+        at_dot(&mut ppu, end - 3);
+        run_cpu_cycle(&mut ppu);
+        assert_eq!((ppu.cycle, ppu.odd_frame), (0, true));
+    }
+
+    #[test]
+    fn coarse_x_wraps_into_the_next_nametable() {
+        let mut ppu = test_ppu(0);
+        ppu.v = 0x001E;
+        increment_scroll_x(&mut ppu);
+        assert_eq!(ppu.v, 0x001F);
+        increment_scroll_x(&mut ppu);
+        assert_eq!(ppu.v, 0x0400);
+        ppu.v = 0x741F;
+        increment_scroll_x(&mut ppu);
+        assert_eq!(ppu.v, 0x7000);
+    }
+
+    #[test]
+    fn fine_y_carries_into_coarse_y_and_wraps_at_29() {
+        let mut ppu = test_ppu(0);
+        ppu.v = 0x0000;
+        increment_scroll_y(&mut ppu);
+        assert_eq!(ppu.v, 0x1000);
+        ppu.v = 0x7000 | (5 << 5);
+        increment_scroll_y(&mut ppu);
+        assert_eq!(ppu.v, 6 << 5);
+        // line 29 is the last row of a nametable, the next one is below it
+        ppu.v = 0x7000 | (29 << 5);
+        increment_scroll_y(&mut ppu);
+        assert_eq!(ppu.v, 0x0800);
+        // rows 30 and 31 hold the attributes, they wrap without switching
+        ppu.v = 0x7800 | (31 << 5);
+        increment_scroll_y(&mut ppu);
+        assert_eq!(ppu.v, 0x0800);
+    }
+
+    // renders from the pre-render line until the given number of lines are done
+    fn render_lines(ppu: &mut Ppu, mask: u8, lines: u32) -> Vec<u16> {
+        let mut canvas = vec![0; 256 * 240];
+        write_io(ppu, 0x2001, mask);
+        ppu.cycle = ppu.prerender_line * 341;
+        while ppu.cycle >= lines * 341 {
+            run(&mut canvas, ppu);
+        }
+        while ppu.cycle < lines * 341 {
+            run(&mut canvas, ppu);
+        }
+        return canvas;
+    }
+
+    #[test]
+    fn scroll_x_moves_the_background_by_coarse_and_fine_x() {
+        let mut ppu = test_ppu(0);
+        set_mirroring(&mut ppu, Mirroring::Vertical);
+        write_vram(&mut ppu, 0x3F01, 0x16);
+        // the rightmost column of tile 3 and of the first tile of the right nametable
+        write_vram(&mut ppu, 0x2003, 2);
+        write_vram(&mut ppu, 0x2400, 2);
+        // coarse x 1, fine x 5
+        write_io(&mut ppu, 0x2000, 0x00);
+        write_io(&mut ppu, 0x2005, 13);
+        write_io(&mut ppu, 0x2005, 0);
+        let canvas = render_lines(&mut ppu, 0x0A, 1);
+        let opaque: Vec<usize> = (0..256).filter(|&x| canvas[x] != 0).collect();
+        assert_eq!(opaque, vec![3 * 8 + 7 - 13, 256 + 7 - 13]);
+        assert_eq!(canvas[18], 0x16);
+
+        // fine x 7 lines the last column of tile 3 up with the left edge
+        write_io(&mut ppu, 0x2005, 31);
+        write_io(&mut ppu, 0x2005, 0);
+        let canvas = render_lines(&mut ppu, 0x0A, 1);
+        let opaque: Vec<usize> = (0..256).filter(|&x| canvas[x] != 0).collect();
+        assert_eq!(opaque, vec![0, 256 + 7 - 31]);
+    }
+
+    #[test]
+    fn attribute_bits_come_from_the_quadrant_of_the_tile() {
+        let mut ppu = test_ppu(0);
+        // top left 0, top right 1, bottom left 2, bottom right 3
+        write_vram(&mut ppu, 0x23C0, 0xE4);
+        write_vram(&mut ppu, 0x23C1, 0x55);
+        write_vram(&mut ppu, 0x23C8, 0xAA);
+        let mut attribute = |v: u16| {
+            ppu.v = v;
+            fetch_bg(&mut ppu, 2);
+            return ppu.attribute_latch;
+        };
+        // bit 1 of coarse x and coarse y pick the quadrant
+        assert_eq!(attribute(0x0000), 0);
+        assert_eq!(attribute(0x0001), 0);
+        assert_eq!(attribute(0x0002), 1);
+        assert_eq!(attribute(0x0040), 2);
+        assert_eq!(attribute(0x0063), 3);
+        // the next 4 columns and the next 4 rows use the next bytes
+        assert_eq!(attribute(0x0004), 1);
+        assert_eq!(attribute(0x0080), 2);
+        // fine y doesn't matter
+        assert_eq!(attribute(0x7063), 3);
+    }
+
+    // writes the table number into each nametable and reads what the four show
+    fn nametables(mirroring: Mirroring) -> Vec<u8> {
+        let mut ppu = test_ppu(0);
+        set_mirroring(&mut ppu, mirroring);
+        for table in 0..4 {
+            write_vram(&mut ppu, 0x2000 + table * 0x0400 + 0x0123, table as u8);
         }
+        return (0..4).map(|table| read_vram(&ppu, 0x2000 + table * 0x0400 + 0x0123)).collect();
+    }
+
+    #[test]
+    fn nametables_follow_the_mirroring() {
+        assert_eq!(nametables(Mirroring::Horizontal), vec![1, 1, 3, 3]);
+        assert_eq!(nametables(Mirroring::Vertical), vec![2, 3, 2, 3]);
+        assert_eq!(nametables(Mirroring::SingleScreenA), vec![3, 3, 3, 3]);
+        assert_eq!(nametables(Mirroring::SingleScreenB), vec![3, 3, 3, 3]);
+        assert_eq!(nametables(Mirroring::FourScreen), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn single_screen_mirrorings_use_different_halves_of_ciram() {
+        let mut ppu = test_ppu(0);
+        set_mirroring(&mut ppu, Mirroring::SingleScreenA);
+        write_vram(&mut ppu, 0x2000, 0x11);
+        set_mirroring(&mut ppu, Mirroring::SingleScreenB);
+        write_vram(&mut ppu, 0x2000, 0x22);
+        assert_eq!(read_vram(&ppu, 0x2C00), 0x22);
+        set_mirroring(&mut ppu, Mirroring::SingleScreenA);
+        assert_eq!(read_vram(&ppu, 0x2C00), 0x11);
+        // $3000-$3EFF mirrors the nametables
+        assert_eq!(read_vram(&ppu, 0x3000), 0x11);
+    }
+
+    // one opaque pixel per tile row, at a different x for every row the tests look at
+    fn sprite_test_ppu() -> Ppu {
+        let mut chr = vec![0; 0x2000];
+        chr[0x0020] = 0x80;
+        chr[0x1020] = 0x40;
+        chr[0x1030] = 0x20;
+        chr[0x1037] = 0x10;
+        chr[0x1027] = 0x08;
+        return new_ppu(&chr);
+    }
+
+    // x of the opaque pixels of the sprites fetched for the line after scanline_y
+    fn sprite_pixels(ppu: &mut Ppu, y: u8, tile: u8, attr: u8, scanline_y: u32) -> Vec<usize> {
+        set_sprite_zero(ppu, y, tile, attr, 100);
+        evaluate_sprites(ppu, scanline_y);
+        return (0..256).filter(|&x| (ppu.sprite_line[x] & SPRITE_PIXEL_COLOUR) != 0).collect();
+    }
+
+    #[test]
+    fn tall_sprites_take_the_pattern_table_from_the_tile_index() {
+        let mut ppu = sprite_test_ppu();
+        // 8x16 ignores the sprite pattern table bit of ppuctrl
+        write_io(&mut ppu, 0x2000, 0x28);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 2, 0x00, 10), vec![100]);
+        // odd tiles use $1000, the top half is the even tile and the bottom half the odd one
+        assert_eq!(sprite_pixels(&mut ppu, 10, 3, 0x00, 10), vec![101]);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 3, 0x00, 18), vec![102]);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 3, 0x00, 26).len(), 0);
+        // 8x8 does use it
+        write_io(&mut ppu, 0x2000, 0x08);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 2, 0x00, 10), vec![101]);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 2, 0x00, 18).len(), 0);
+    }
+
+    #[test]
+    fn flips_mirror_the_whole_sprite() {
+        let mut ppu = sprite_test_ppu();
+        write_io(&mut ppu, 0x2000, 0x20);
+        // a vertical flip of a tall sprite swaps the two tiles too
+        assert_eq!(sprite_pixels(&mut ppu, 10, 3, 0x80, 10), vec![103]);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 3, 0x80, 18), vec![104]);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 3, 0x40, 10), vec![106]);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 3, 0xC0, 25), vec![106]);
+        write_io(&mut ppu, 0x2000, 0x08);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 2, 0x80, 17), vec![101]);
+        assert_eq!(sprite_pixels(&mut ppu, 10, 3, 0x80, 10), vec![103]);
     }
-}
\ No newline at end of file
+
+    // fills oam with the given y and tile bytes, the rest of it is off screen
+    fn overflows(sprites: &[(u8, u8)]) -> bool {
+        let mut ppu = sprite_test_ppu();
+        for byte in ppu.oam.iter_mut() {
+            *byte = 0xFF;
+        }
+        for (i, &(y, tile)) in sprites.iter().enumerate() {
+            ppu.oam[i * 4] = y;
+            ppu.oam[i * 4 + 1] = tile;
+        }
+        find_sprites(&mut ppu, 10);
+        return (ppu.reg_status & 0x20) != 0;
+    }
+
+    #[test]
+    fn overflow_flag_scans_oam_diagonally_after_8_sprites() {
+        let eight = [(10, 0xFF); 8];
+        assert!(!overflows(&eight));
+        assert!(overflows(&[eight.to_vec(), vec![(5, 0xFF)]].concat()));
+        // after a miss the scan reads the tile of the next sprite as its y
+        assert!(overflows(&[eight.to_vec(), vec![(200, 0xFF), (200, 10)]].concat()));
+        assert!(!overflows(&[eight.to_vec(), vec![(200, 0xFF), (10, 0xFF)]].concat()));
+        // fewer than 8 sprites on the line never set it
+        assert!(!overflows(&[(10, 0xFF), (200, 10), (200, 10)]));
+    }
+
+    fn set_vram_address(ppu: &mut Ppu, addr: u16) {
+        write_io(ppu, 0x2006, (addr >> 8) as u8);
+        write_io(ppu, 0x2006, addr as u8);
+    }
+
+    #[test]
+    fn data_reads_return_the_previous_byte() {
+        let mut ppu = test_ppu(0);
+        write_vram(&mut ppu, 0x2100, 0x11);
+        write_vram(&mut ppu, 0x2101, 0x22);
+        set_vram_address(&mut ppu, 0x2100);
+        // the first read returns the stale buffer
+        assert_eq!(read_io(&mut ppu, 0x2007), 0x00);
+        assert_eq!(read_io(&mut ppu, 0x2007), 0x11);
+        assert_eq!(read_io(&mut ppu, 0x2007), 0x22);
+        assert_eq!(ppu.v, 0x2103);
+        // so does the pattern table
+        set_vram_address(&mut ppu, 0x0010);
+        read_io(&mut ppu, 0x2007);
+        assert_eq!(read_io(&mut ppu, 0x2007), 0xFF);
+    }
+
+    #[test]
+    fn palette_reads_skip_the_buffer() {
+        let mut ppu = test_ppu(0);
+        write_vram(&mut ppu, 0x3F05, 0x2A);
+        write_vram(&mut ppu, 0x2F05, 0x33);
+        set_vram_address(&mut ppu, 0x3F05);
+        assert_eq!(read_io(&mut ppu, 0x2007) & 0x3F, 0x2A);
+        // the buffer got the nametable byte under the palette
+        assert_eq!(ppu.read_buffer, 0x33);
+        // the top 2 bits are open bus
+        write_io(&mut ppu, 0x2002, 0xC0);
+        set_vram_address(&mut ppu, 0x3F05);
+        write_io(&mut ppu, 0x2002, 0xC0);
+        assert_eq!(read_io(&mut ppu, 0x2007), 0xEA);
+    }
+
+    #[test]
+    fn data_access_increments_by_1_or_32() {
+        let mut ppu = test_ppu(0);
+        set_vram_address(&mut ppu, 0x2000);
+        write_io(&mut ppu, 0x2007, 0x01);
+        write_io(&mut ppu, 0x2000, 0x04);
+        write_io(&mut ppu, 0x2007, 0x02);
+        write_io(&mut ppu, 0x2007, 0x03);
+        assert_eq!(ppu.v, 0x2041);
+        assert_eq!(read_vram(&ppu, 0x2001), 0x02);
+        assert_eq!(read_vram(&ppu, 0x2021), 0x03);
+        // reads increment the same way
+        read_io(&mut ppu, 0x2007);
+        assert_eq!(ppu.v, 0x2061);
+        // the address wraps at $3FFF into the pattern tables
+        set_vram_address(&mut ppu, 0x3FF0);
+        read_io(&mut ppu, 0x2007);
+        assert_eq!(ppu.v & 0x3FFF, 0x0010);
+    }
+
+    #[test]
+    fn greyscale_keeps_the_brightness_column() {
+        let mut ppu = test_ppu(0);
+        write_io(&mut ppu, 0x2001, 0x01);
+        assert_eq!(apply_mask(&ppu, 0x16), 0x10);
+        assert_eq!(apply_mask(&ppu, 0x3D), 0x30);
+        write_io(&mut ppu, 0x2001, 0x00);
+        assert_eq!(apply_mask(&ppu, 0x16), 0x16);
+    }
+
+    #[test]
+    fn emphasis_bits_go_above_the_colour() {
+        let mut ppu = test_ppu(0);
+        // red, green and blue on the 2C02
+        write_io(&mut ppu, 0x2001, 0x20);
+        assert_eq!(apply_mask(&ppu, 0x16), 0x16 | 0x040);
+        write_io(&mut ppu, 0x2001, 0x40);
+        assert_eq!(apply_mask(&ppu, 0x16), 0x16 | 0x080);
+        write_io(&mut ppu, 0x2001, 0xE1);
+        assert_eq!(apply_mask(&ppu, 0x16), 0x10 | 0x1C0);
+        // the 2C07 swaps red and green
+        set_region(&mut ppu, region::Region::Pal);
+        write_io(&mut ppu, 0x2001, 0x20);
+        assert_eq!(apply_mask(&ppu, 0x16), 0x16 | 0x080);
+        write_io(&mut ppu, 0x2001, 0x80);
+        assert_eq!(apply_mask(&ppu, 0x16), 0x16 | 0x100);
+    }
+
+    #[test]
+    fn left_column_clipping_shows_the_backdrop() {
+        let mut ppu = test_ppu(1);
+        write_vram(&mut ppu, 0x3F00, 0x0F);
+        write_vram(&mut ppu, 0x3F01, 0x16);
+        write_vram(&mut ppu, 0x3F11, 0x2A);
+        // a solid sprite over the left edge of line 1
+        set_sprite_zero(&mut ppu, 0, 1, 0x00, 4);
+        let row = |canvas: &Vec<u16>| canvas[256..256 + 16].to_vec();
+
+        let canvas = render_lines(&mut ppu, 0x0A, 2);
+        assert_eq!(row(&canvas), vec![0x16; 16]);
+        let canvas = render_lines(&mut ppu, 0x08, 2);
+        assert_eq!(row(&canvas), [vec![0x0F; 8], vec![0x16; 8]].concat());
+
+        let canvas = render_lines(&mut ppu, 0x14, 2);
+        assert_eq!(row(&canvas), [vec![0x0F; 4], vec![0x2A; 8], vec![0x0F; 4]].concat());
+        let canvas = render_lines(&mut ppu, 0x10, 2);
+        assert_eq!(row(&canvas), [vec![0x0F; 8], vec![0x2A; 4], vec![0x0F; 4]].concat());
+
+        // with both layers clipped the left 8 pixels show only the backdrop
+        let canvas = render_lines(&mut ppu, 0x18, 2);
+        assert_eq!(row(&canvas), [vec![0x0F; 8], vec![0x2A; 4], vec![0x16; 4]].concat());
+    }
+
+    // cpu cycles the vblank flag stays set and from one vblank to the next
+    fn vblank_and_frame_cycles(region: region::Region) -> (u32, u32) {
+        let mut ppu = test_ppu(0);
+        set_region(&mut ppu, region);
+        while (ppu.reg_status & 0x80) == 0 {
+            run_cpu_cycle(&mut ppu);
+        }
+        let mut vblank = 0;
+        while (ppu.reg_status & 0x80) != 0 {
+            run_cpu_cycle(&mut ppu);
+            vblank += 1;
+        }
+        let mut frame = vblank;
+        while (ppu.reg_status & 0x80) == 0 {
+            run_cpu_cycle(&mut ppu);
+            frame += 1;
+        }
+        return (vblank, frame);
+    }
+
+    #[test]
+    fn regions_set_the_scanlines_and_the_vblank_length() {
+        let mut ppu = test_ppu(0);
+        set_region(&mut ppu, region::Region::Pal);
+        assert_eq!((ppu.prerender_line, ppu.vblank_start), (311, 241 * 341 + 1));
+        set_region(&mut ppu, region::Region::Dendy);
+        assert_eq!((ppu.prerender_line, ppu.vblank_start), (311, 291 * 341 + 1));
+        set_region(&mut ppu, region::Region::Ntsc);
+        assert_eq!((ppu.prerender_line, ppu.vblank_start), (261, 241 * 341 + 1));
+
+        // 20 lines of vblank at 3 dots a cycle, 29780.67 cycles a frame
+        let (vblank, frame) = vblank_and_frame_cycles(region::Region::Ntsc);
+        assert!(vblank == 2273 || vblank == 2274, "ntsc vblank {}", vblank);
+        assert!(frame == 29780 || frame == 29781, "ntsc frame {}", frame);
+        // 70 lines at 3.2 dots a cycle, 33247.5 a frame
+        let (vblank, frame) = vblank_and_frame_cycles(region::Region::Pal);
+        assert!(vblank == 7459 || vblank == 7460, "pal vblank {}", vblank);
+        assert!(frame == 33247 || frame == 33248, "pal frame {}", frame);
+        // 20 lines like ntsc after 50 more idle lines, 35464 a frame
+        let (vblank, frame) = vblank_and_frame_cycles(region::Region::Dendy);
+        assert!(vblank == 2273 || vblank == 2274, "dendy vblank {}", vblank);
+        assert_eq!(frame, 35464);
+    }
+}
@@ -1,3 +1,5 @@
+use super::super::region;
+
 pub const PALETTE_TABLE: [u8; 64*3] = [
     124,124,124,
     0,0,252,
@@ -63,4 +65,266 @@ pub const PALETTE_TABLE: [u8; 64*3] = [
     248,216,248,
     0,0,0,
     0,0,0,
-];
\ No newline at end of file
+];
+
+// 2C07, decoded as PAL composite video
+pub const PAL_PALETTE_TABLE: [u8; 64*3] = [
+    107,107,107,
+    17,40,116,
+    43,25,126,
+    67,13,116,
+    84,10,86,
+    91,16,43,
+    84,29,0,
+    67,45,0,
+    43,60,0,
+    17,70,0,
+    0,72,0,
+    0,67,43,
+    0,56,86,
+    0,0,0,
+    0,0,0,
+    0,0,0,
+    177,177,177,
+    62,92,188,
+    94,72,202,
+    125,59,188,
+    148,55,149,
+    156,62,94,
+    148,77,36,
+    125,97,0,
+    94,116,0,
+    62,129,0,
+    38,132,36,
+    28,126,94,
+    38,111,149,
+    0,0,0,
+    0,0,0,
+    0,0,0,
+    255,255,255,
+    142,170,255,
+    173,151,255,
+    203,138,255,
+    225,135,227,
+    233,141,173,
+    225,156,117,
+    203,176,74,
+    173,194,58,
+    142,207,74,
+    119,210,117,
+    110,204,173,
+    119,189,227,
+    87,87,87,
+    0,0,0,
+    0,0,0,
+    255,255,255,
+    210,221,255,
+    222,214,255,
+    234,209,255,
+    243,208,244,
+    246,210,222,
+    243,216,201,
+    234,224,185,
+    222,231,179,
+    210,236,185,
+    201,237,201,
+    198,235,222,
+    201,229,244,
+    189,189,189,
+    0,0,0,
+    0,0,0,
+];
+
+pub fn default_palette(region: region::Region) -> &'static [u8; 64*3] {
+    match region {
+        region::Region::Ntsc => return &PALETTE_TABLE,
+        region::Region::Pal | region::Region::Dendy => return &PAL_PALETTE_TABLE,
+    }
+}
+
+// attenuation of the channels that are not emphasized
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+
+// expands a 64 colour palette to the 512 entries addressed by the 9 bit
+// colour index, bit 6 emphasizes red, bit 7 green and bit 8 blue
+pub fn new_emphasis_table(base: &[u8]) -> Vec<u8> {
+    let mut table = vec![0; 512 * 3];
+    for emphasis in 0..8 {
+        for colour in 0..64 {
+            let offset = ((emphasis << 6) | colour) * 3;
+            for channel in 0..3 {
+                let mut value = base[colour * 3 + channel] as f32;
+                // the black columns $xE and $xF are not affected
+                if (colour & 0x0E) != 0x0E {
+                    for bit in 0..3 {
+                        if (emphasis & (1 << bit)) != 0 && bit != channel {
+                            value = value * EMPHASIS_ATTENUATION;
+                        }
+                    }
+                }
+                table[offset + channel] = value as u8;
+            }
+        }
+    }
+    return table;
+}
+
+// converts the 9 bit colour indices of a frame to RGBA
+pub fn to_rgba(table: &[u8], frame: &[u16], rgba: &mut [u8]) {
+    for i in 0..frame.len() {
+        let offset = ((frame[i] & 0x1FF) as usize) * 3;
+        rgba[i * 4] = table[offset];
+        rgba[i * 4 + 1] = table[offset + 1];
+        rgba[i * 4 + 2] = table[offset + 2];
+        rgba[i * 4 + 3] = 0xFF;
+    }
+}
+
+// .pal files hold 64 colours, or 512 colours with every emphasis combination
+pub fn load_pal(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    match data.len() {
+        192 => return Ok(new_emphasis_table(data)),
+        1536 => return Ok(data.to_vec()),
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("palette must be 192 or 1536 bytes, got {}", data.len())
+                ));
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PalettePreset {
+    // composite output, decoded with the default ntsc parameters
+    Rp2C02,
+    // RGB output of the PlayChoice-10 and Famicom Titler. the 2C05 of the VS.
+    // System outputs the same colours and only differs in its registers
+    Rp2C03,
+}
+
+// 3 bit red, green and blue levels of the RGB PPUs
+const RGB_PPU_TABLE: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+// the RGB PPUs drive an emphasized channel to full level instead of darkening the others
+fn new_rgb_ppu_table() -> Vec<u8> {
+    let mut table = vec![0; 512 * 3];
+    for emphasis in 0..8 {
+        for colour in 0..64 {
+            let offset = ((emphasis << 6) | colour) * 3;
+            for channel in 0..3 {
+                let mut level = (RGB_PPU_TABLE[colour] >> ((2 - channel) * 3)) & 0x07;
+                if (emphasis & (1 << channel)) != 0 {
+                    level = 7;
+                }
+                table[offset + channel] = ((level as u32) * 255 / 7) as u8;
+            }
+        }
+    }
+    return table;
+}
+
+pub fn preset_palette(preset: PalettePreset) -> Vec<u8> {
+    match preset {
+        PalettePreset::Rp2C02 => return generate_ntsc_palette(&new_ntsc_parameters()),
+        PalettePreset::Rp2C03 => return new_rgb_ppu_table(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NtscParameters {
+    // in degrees
+    pub hue: f64,
+    pub saturation: f64,
+    pub contrast: f64,
+    pub brightness: f64,
+    // gamma of the display the palette is corrected for, 2.2 leaves the decoded signal as is
+    pub gamma: f64,
+}
+
+pub fn new_ntsc_parameters() -> NtscParameters {
+    return NtscParameters {
+        hue: 0.0,
+        saturation: 1.0,
+        contrast: 1.0,
+        brightness: 0.0,
+        gamma: 2.2,
+    };
+}
+
+// composite levels in volts of luma 0-3 for the low and high half of the
+// colour wave, measured on a 2C02
+const SIGNAL_LOW: [f64; 4] = [0.228, 0.312, 0.552, 0.880];
+const SIGNAL_HIGH: [f64; 4] = [0.616, 0.840, 1.100, 1.100];
+const SIGNAL_BLACK: f64 = 0.312;
+const SIGNAL_WHITE: f64 = 1.100;
+const SIGNAL_EMPHASIS_ATTENUATION: f64 = 0.746;
+// phase offset in twelfths of a colour cycle that lines the decoder up with the colour burst
+pub const SIGNAL_HUE_OFFSET: f64 = 4.0;
+
+// the colour is high for 6 of the 12 phases of the colour subcarrier
+fn is_in_colour_phase(colour: usize, phase: usize) -> bool {
+    return (colour + phase) % 12 < 6;
+}
+
+// composite level of a 9 bit colour index at one of the 12 subcarrier phases, 0.0 is black and 1.0 white
+pub fn ntsc_signal(index: usize, phase: usize) -> f64 {
+    let colour = index & 0x0F;
+    let luma = (index >> 4) & 0x03;
+    let emphasis = index >> 6;
+    let mut level = match colour {
+        0x00 => SIGNAL_HIGH[luma],
+        0x0D => SIGNAL_LOW[luma],
+        0x0E | 0x0F => SIGNAL_BLACK,
+        _ => if is_in_colour_phase(colour, phase) { SIGNAL_HIGH[luma] } else { SIGNAL_LOW[luma] },
+    };
+    // emphasis darkens the signal during the phases of red ($x0), green ($x4) and blue ($x8)
+    let attenuate = ((emphasis & 0x01) != 0 && is_in_colour_phase(0, phase))
+        || ((emphasis & 0x02) != 0 && is_in_colour_phase(4, phase))
+        || ((emphasis & 0x04) != 0 && is_in_colour_phase(8, phase));
+    if attenuate && colour < 0x0E {
+        level = level * SIGNAL_EMPHASIS_ATTENUATION;
+    }
+    return (level - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK);
+}
+
+// decodes one colour cycle of every 9 bit colour index to RGB
+pub fn generate_ntsc_palette(params: &NtscParameters) -> Vec<u8> {
+    let mut table = vec![0; 512 * 3];
+    for index in 0..512 {
+        let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+        for phase in 0..12 {
+            let signal = ntsc_signal(index, phase) / 12.0;
+            let angle = std::f64::consts::PI * ((phase as f64) + SIGNAL_HUE_OFFSET) / 6.0 + params.hue.to_radians();
+            y += signal;
+            i += signal * angle.cos();
+            q += signal * angle.sin();
+        }
+        let rgb = yiq_to_rgb(params, y, i, q);
+        table[index * 3..index * 3 + 3].copy_from_slice(&rgb);
+    }
+    return table;
+}
+
+// applies the picture controls and converts with the FCC matrix
+pub fn yiq_to_rgb(params: &NtscParameters, y: f64, i: f64, q: f64) -> [u8; 3] {
+    let y = y * params.contrast + params.brightness;
+    let i = i * params.saturation * params.contrast;
+    let q = q * params.saturation * params.contrast;
+    let rgb = [
+        y + 0.946882 * i + 0.623557 * q,
+        y - 0.274788 * i - 0.635691 * q,
+        y - 1.108545 * i + 1.709007 * q,
+    ];
+    let mut out = [0; 3];
+    for channel in 0..3 {
+        let value = rgb[channel].max(0.0).min(1.0).powf(2.2 / params.gamma);
+        out[channel] = (value * 255.0).round() as u8;
+    }
+    return out;
+}
@@ -0,0 +1,39 @@
+// console timing variants, the header or the user picks one before power on
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Region {
+    // 2C02 and 2A03, 3 ppu dots per cpu cycle
+    Ntsc,
+    // 2C07 and 2A07, 3.2 ppu dots per cpu cycle
+    Pal,
+    // famiclone with a PAL clock but NTSC style cpu/ppu ratio and a long post-render period
+    Dendy,
+}
+
+pub fn cpu_clock(region: Region) -> f64 {
+    match region {
+        Region::Ntsc => return 1789773.0,
+        Region::Pal => return 1662607.0,
+        Region::Dendy => return 1773448.0,
+    }
+}
+
+pub fn frame_rate(region: Region) -> f64 {
+    match region {
+        Region::Ntsc => return 60.0988,
+        Region::Pal => return 50.0070,
+        Region::Dendy => return 50.0070,
+    }
+}
+
+// exact frames per second as numerator and denominator, the cpu clock over
+// the cpu cycles of a frame, for timestamps that do not drift in recordings
+pub fn frame_rate_ratio(region: Region) -> (u64, u64) {
+    match region {
+        // 29780.5 cycles, the skipped dot of odd frames averages out
+        Region::Ntsc => return (1789773 * 2, 59561),
+        // 33247.5 cycles
+        Region::Pal => return (1662607 * 2, 66495),
+        // 312 lines of 341 dots at 3 dots per cycle
+        Region::Dendy => return (1773448, 35464),
+    }
+}
@@ -3,6 +3,8 @@ use std::io::prelude::*;
 use std::path::Path;
 use std::str;
 use std::error::Error;
+use super::ppu;
+use super::region;
 
 #[derive(Debug)]
 pub struct NesHeader {
@@ -13,6 +15,7 @@ pub struct NesHeader {
     pub flag8: u8,
     pub flag9: u8,
     pub flag10: u8,
+    pub flag12: u8,
 }
 
 pub struct CharacterRom {
@@ -31,6 +34,37 @@ pub struct NesRom {
 
 const NES_HEADER_SIZE: usize = 0x10;
 
+// nametable arrangement wired on the board, mappers may switch it at runtime
+pub fn get_mirroring(header: &NesHeader) -> ppu::Mirroring {
+    if (header.flag6 & 0x08) != 0 {
+        return ppu::Mirroring::FourScreen;
+    }
+    if (header.flag6 & 0x01) != 0 {
+        return ppu::Mirroring::Vertical;
+    }
+    return ppu::Mirroring::Horizontal;
+}
+
+// the low nibble is in flag 6 and the high nibble in flag 7
+pub fn get_mapper(header: &NesHeader) -> u16 {
+    return ((header.flag7 & 0xF0) | (header.flag6 >> 4)) as u16;
+}
+
+// NES 2.0 stores the cpu/ppu timing in byte 12, iNES only has the rarely set PAL bit of flag 9
+pub fn get_region(header: &NesHeader) -> region::Region {
+    if (header.flag7 & 0x0C) == 0x08 {
+        match header.flag12 & 0x03 {
+            1 => return region::Region::Pal,
+            3 => return region::Region::Dendy,
+            _ => return region::Region::Ntsc,
+        }
+    }
+    if (header.flag9 & 0x01) != 0 {
+        return region::Region::Pal;
+    }
+    return region::Region::Ntsc;
+}
+
 fn load_program_rom(buffer: &[u8], header: &NesHeader) -> Result<ProgramRom, std::io::Error> {
     let start: usize = NES_HEADER_SIZE;
     let end = start + header.size_of_prg_rom as usize; 
@@ -65,6 +99,7 @@ fn load_nes_header(buffer: &[u8]) -> Result<NesHeader, std::io::Error> {
         flag8: header[4],
         flag9: header[5],
         flag10: header[6],
+        flag12: header[8],
     })
 }
 
@@ -145,4 +180,49 @@ pub fn load_nes(rom_buffer: &Vec<u8>) -> NesRom {
             return nes_rom;
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_header(flag6: u8, flag7: u8, flag9: u8, flag12: u8) -> NesHeader {
+        return NesHeader {
+            size_of_prg_rom: 0x4000,
+            size_of_chr_rom: 0x2000,
+            flag6: flag6,
+            flag7: flag7,
+            flag8: 0,
+            flag9: flag9,
+            flag10: 0,
+            flag12: flag12,
+        };
+    }
+
+    #[test]
+    fn mirroring_comes_from_flag_6() {
+        assert_eq!(get_mirroring(&test_header(0x00, 0, 0, 0)), ppu::Mirroring::Horizontal);
+        assert_eq!(get_mirroring(&test_header(0x01, 0, 0, 0)), ppu::Mirroring::Vertical);
+        // four screen wins over the mirroring bit
+        assert_eq!(get_mirroring(&test_header(0x08, 0, 0, 0)), ppu::Mirroring::FourScreen);
+        assert_eq!(get_mirroring(&test_header(0x09, 0, 0, 0)), ppu::Mirroring::FourScreen);
+        // the mapper and battery bits don't matter
+        assert_eq!(get_mirroring(&test_header(0x13, 0, 0, 0)), ppu::Mirroring::Vertical);
+    }
+
+    #[test]
+    fn region_comes_from_nes_2_0_byte_12_or_the_ines_pal_bit() {
+        // NES 2.0 is flagged by bits 2-3 of flag 7
+        assert_eq!(get_region(&test_header(0, 0x08, 0, 0)), region::Region::Ntsc);
+        assert_eq!(get_region(&test_header(0, 0x08, 0, 1)), region::Region::Pal);
+        // multiple region roms run as ntsc
+        assert_eq!(get_region(&test_header(0, 0x08, 0, 2)), region::Region::Ntsc);
+        assert_eq!(get_region(&test_header(0, 0x08, 0, 3)), region::Region::Dendy);
+        // byte 12 overrides the iNES bit
+        assert_eq!(get_region(&test_header(0, 0x08, 1, 0)), region::Region::Ntsc);
+        // iNES only knows PAL
+        assert_eq!(get_region(&test_header(0, 0x00, 0, 3)), region::Region::Ntsc);
+        assert_eq!(get_region(&test_header(0, 0x00, 1, 0)), region::Region::Pal);
+        assert_eq!(get_region(&test_header(0, 0x04, 1, 0)), region::Region::Pal);
+    }
+}
@@ -0,0 +1,23 @@
+use super::cpu;
+use super::vmem;
+use super::ppu;
+use super::apu;
+use super::input;
+
+// runs the console until the ppu reaches vblank, frame receives the colour indices
+pub fn run_frame(cpu: &mut cpu::Cpu, mem: &mut vmem::Vmem, frame: &mut Vec<u16>) {
+    loop {
+        cpu::run(cpu, mem);
+        vmem::run_apu(mem);
+        let stall = vmem::run_dma(mem, cpu::is_reading(cpu));
+        cpu::stall(cpu, stall);
+        ppu::run(frame, &mut mem.ppu);
+
+        if ppu::is_draw_timing(mem.ppu) {
+            ppu::check_drawn(&mut mem.ppu);
+            apu::end_frame(&mut mem.apu);
+            input::clock_frame(&mut mem.mem.input);
+            break;
+        }
+    }
+}
@@ -1,16 +1,19 @@
 use super::memory;
 use super::ppu;
-use web_sys::console;
+use super::apu;
+use super::input;
 
-pub struct Vmem<'a, 'b> {
+pub struct Vmem<'a, 'b, 'c> {
     pub mem: &'a mut memory::Memory,
     pub ppu: &'b mut ppu::Ppu,
+    pub apu: &'c mut apu::Apu,
 }
 
-pub fn new_vmem<'a, 'b>(mem: &'a mut memory::Memory, ppu: &'b mut ppu::Ppu) -> Vmem<'a, 'b> {
+pub fn new_vmem<'a, 'b, 'c>(mem: &'a mut memory::Memory, ppu: &'b mut ppu::Ppu, apu: &'c mut apu::Apu) -> Vmem<'a, 'b, 'c> {
     return Vmem {
         mem: mem,
         ppu: ppu,
+        apu: apu,
     };
 }
 
@@ -27,24 +30,182 @@ pub fn write_mem_word(vmem: &mut Vmem, addr: u16, data: u16) {
 
 pub fn read_mem(mem: &mut Vmem, addr: u16) -> u8 {
     let mut value = 0u8;
-    if (addr >= 0x2000 && addr < 0x2008) || addr == 0x4014 {
-        // ppu
-        value = ppu::read_io(&mut mem.ppu, addr);
+    if addr >= 0x2000 && addr < 0x4000 {
+        // ppu, mirrored every 8 bytes
+        value = ppu::read_io(&mut mem.ppu, 0x2000 | (addr & 0x07));
+    } else if addr == 0x4014 {
+        // oam dma is write only
+        value = 0;
+    } else if addr == 0x4015 {
+        // apu
+        value = apu::read_io(&mut mem.apu, addr);
+    } else if addr == 0x4016 || addr == 0x4017 {
+        // controllers
+        value = input::read_port(&mut mem.mem.input, (addr & 0x01) as usize, mem.mem.open_bus);
     } else {
         // cpu
         value = memory::read_mem(&mut mem.mem, addr);
     }
+    mem.mem.open_bus = value;
+    mem.mem.last_read = addr;
     // console::log_1(&format!("read {:04X?} value:{:02X}", addr, value).into());
     return value;
 }
 
 pub fn write_mem(mem: &mut Vmem, addr: u16, value: u8) {
     // console::log_1(&format!("write {:04X} value:{:02X}", addr, value).into());
-    if (addr >= 0x2000 && addr < 0x2008) || addr == 0x4014 {
-        // ppu
+    mem.mem.open_bus = value;
+    if addr >= 0x2000 && addr < 0x4000 {
+        // ppu, mirrored every 8 bytes
+        ppu::write_io(&mut mem.ppu, 0x2000 | (addr & 0x07), value);
+    } else if addr == 0x4014 {
         ppu::write_io(&mut mem.ppu, addr, value);
+    } else if addr == 0x4016 {
+        // controller strobe, $4017 writes go to the apu frame counter
+        input::write_strobe(&mut mem.mem.input, value);
+    } else if (addr >= 0x4000 && addr < 0x4014) || addr == 0x4015 || addr == 0x4017 {
+        // apu
+        apu::write_io(&mut mem.apu, addr, value);
     } else {
         // cpu
         memory::write_mem(&mut mem.mem, addr, value);
     }
-}
\ No newline at end of file
+}
+
+pub fn is_irq(mem: &Vmem) -> bool {
+    return apu::is_irq(&mem.apu);
+}
+
+pub fn take_nmi(mem: &mut Vmem) -> bool {
+    return ppu::take_nmi(&mut mem.ppu);
+}
+
+pub fn run_apu(mem: &mut Vmem) {
+    memory::run_expansion_audio(&mut mem.mem);
+    apu::run(&mut mem.apu, mem.mem.expansion_audio.as_deref());
+}
+
+// performs pending dma transfers and returns the number of cpu cycles to stall,
+// halted_on_read is whether the cpu was halted on the read cycle of its instruction
+pub fn run_dma(mem: &mut Vmem, halted_on_read: bool) -> u16 {
+    let mut stall = 0;
+    if mem.mem.oam_dma_cycles > 0 {
+        mem.mem.oam_dma_cycles -= 1;
+    }
+    if let Some(page) = ppu::oam_dma_request(&mut mem.ppu) {
+        // 256 reads and writes through $2004, plus one halt cycle and one
+        // more to align with a get cycle when started on an odd cycle
+        let base = (page as u16) << 8;
+        for offset in 0..0x100 {
+            let value = read_mem(mem, base + offset);
+            ppu::write_io(&mut mem.ppu, 0x2004, value);
+        }
+        let cycles = if apu::is_odd_cycle(&mem.apu) { 514 } else { 513 };
+        mem.mem.oam_dma_cycles = cycles;
+        stall += cycles;
+    }
+    if let Some(addr) = apu::dmc_dma_request(&mem.apu) {
+        // a cpu halted on the final read of its instruction repeats that read,
+        // for a controller port it shifts out an extra bit the program never
+        // sees. this is approximate: the cpu runs an instruction's accesses
+        // together so the halt can only be placed per instruction, and only
+        // requests arriving in the cycle the instruction ran count as landing
+        // on its read
+        let halted_read = mem.mem.last_read;
+        if halted_on_read && (halted_read == 0x4016 || halted_read == 0x4017) {
+            let open_bus = mem.mem.open_bus;
+            input::read_port(&mut mem.mem.input, (halted_read & 0x01) as usize, open_bus);
+        }
+        let value = read_mem(mem, addr);
+        apu::dmc_dma_fill(&mut mem.apu, value);
+        // a dmc fetch that lands inside an oam dma reuses its halt and
+        // alignment cycles and only costs 2 extra cycles
+        stall += if mem.mem.oam_dma_cycles > 0 { 2 } else { 4 };
+    }
+    return stall;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_parts() -> (memory::Memory, ppu::Ppu, apu::Apu) {
+        return (memory::new_memory(&vec![0; 0x8000]), ppu::new_ppu(&vec![]), apu::new_apu());
+    }
+
+    // a one byte sample at $C000, its fetch is pending until run_dma
+    fn start_dmc(vmem: &mut Vmem) {
+        write_mem(vmem, 0x4012, 0x00);
+        write_mem(vmem, 0x4013, 0x00);
+        write_mem(vmem, 0x4015, 0x10);
+    }
+
+    #[test]
+    fn oam_dma_copies_the_page_and_stalls_513_or_514_cycles() {
+        let (mut mem, mut ppu, mut apu) = test_parts();
+        let mut vmem = new_vmem(&mut mem, &mut ppu, &mut apu);
+        for offset in 0..0x100 {
+            vmem.mem.wram[0x0200 + offset] = offset as u8;
+        }
+        write_mem(&mut vmem, 0x4014, 0x02);
+        assert_eq!(run_dma(&mut vmem, false), 513);
+        write_mem(&mut vmem, 0x2003, 0x05);
+        assert_eq!(read_mem(&mut vmem, 0x2004), 0x05);
+        // nothing more until the next write
+        assert_eq!(run_dma(&mut vmem, false), 0);
+        // on an odd cycle it waits one more to line up with a get cycle
+        apu::run(&mut vmem.apu, None);
+        write_mem(&mut vmem, 0x4014, 0x02);
+        assert_eq!(run_dma(&mut vmem, false), 514);
+    }
+
+    #[test]
+    fn dmc_fetch_costs_4_cycles_or_2_inside_an_oam_dma() {
+        let (mut mem, mut ppu, mut apu) = test_parts();
+        let mut vmem = new_vmem(&mut mem, &mut ppu, &mut apu);
+        start_dmc(&mut vmem);
+        assert_eq!(run_dma(&mut vmem, false), 4);
+        assert_eq!(apu::dmc_dma_request(&vmem.apu), None);
+
+        // in the same cycle as the oam dma starts
+        let (mut mem, mut ppu, mut apu) = test_parts();
+        let mut vmem = new_vmem(&mut mem, &mut ppu, &mut apu);
+        start_dmc(&mut vmem);
+        write_mem(&mut vmem, 0x4014, 0x02);
+        assert_eq!(run_dma(&mut vmem, false), 513 + 2);
+
+        // and while it is running
+        let (mut mem, mut ppu, mut apu) = test_parts();
+        let mut vmem = new_vmem(&mut mem, &mut ppu, &mut apu);
+        vmem.mem.oam_dma_cycles = 100;
+        start_dmc(&mut vmem);
+        assert_eq!(run_dma(&mut vmem, false), 2);
+
+        // but not in its last cycle
+        let (mut mem, mut ppu, mut apu) = test_parts();
+        let mut vmem = new_vmem(&mut mem, &mut ppu, &mut apu);
+        vmem.mem.oam_dma_cycles = 1;
+        start_dmc(&mut vmem);
+        assert_eq!(run_dma(&mut vmem, false), 4);
+    }
+
+    // strobes A and select, reads A and lets the dmc fetch halt the cpu right after it
+    fn second_button(halted_on_read: bool) -> u8 {
+        let (mut mem, mut ppu, mut apu) = test_parts();
+        let mut vmem = new_vmem(&mut mem, &mut ppu, &mut apu);
+        input::set_buttons(&mut vmem.mem.input, 0, input::BUTTON_A | input::BUTTON_SELECT);
+        write_mem(&mut vmem, 0x4016, 1);
+        write_mem(&mut vmem, 0x4016, 0);
+        assert_eq!(read_mem(&mut vmem, 0x4016) & 0x01, 1);
+        start_dmc(&mut vmem);
+        run_dma(&mut vmem, halted_on_read);
+        return read_mem(&mut vmem, 0x4016) & 0x01;
+    }
+
+    #[test]
+    fn dmc_fetch_on_a_controller_read_loses_a_bit() {
+        // B reads 0, unless the repeated read shifted it out and select shows up
+        assert_eq!(second_button(false), 0);
+        assert_eq!(second_button(true), 1);
+    }
+}
@@ -0,0 +1,211 @@
+// writes emulator output to video and audio files frame by frame, the
+// timestamps come from the frame count so a recording is the same however
+// fast it was made. input logs drive the controllers of such runs
+pub mod gif;
+pub mod y4m;
+pub mod wav;
+pub mod input_log;
+
+use super::video::png;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Gif,
+    // animated PNG, lossless with exact frame delays
+    Apng,
+    // uncompressed, for piping into other encoders
+    Y4m,
+}
+
+pub fn format_from_path(path: &str) -> Option<Format> {
+    let extension = std::path::Path::new(path).extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "gif" => return Some(Format::Gif),
+        "png" | "apng" => return Some(Format::Apng),
+        "y4m" => return Some(Format::Y4m),
+        _ => return None,
+    }
+}
+
+// browsers show gif delays below 2/100 s as 1/10 s
+const GIF_MIN_DELAY: u64 = 2;
+// signature and IHDR come before the acTL
+const APNG_ANIMATION_CONTROL_OFFSET: u64 = 8 + 25;
+
+pub struct Recorder {
+    format: Format,
+    out: BufWriter<File>,
+    // frames per second as numerator and denominator
+    rate: (u64, u64),
+    width: usize,
+    height: usize,
+    frames: u64,
+    // frames that made it into a gif
+    written: u32,
+    // apng chunk sequence number
+    sequence: u32,
+    // the gif frame waiting for the next kept one to know its delay, with its start in 1/100 s
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+pub fn new_recorder(path: &str, format: Format, rate: (u64, u64)) -> std::io::Result<Recorder> {
+    let file = File::create(path)?;
+    return Ok(Recorder {
+        format: format,
+        out: BufWriter::new(file),
+        rate: rate,
+        width: 0,
+        height: 0,
+        frames: 0,
+        written: 0,
+        sequence: 0,
+        pending: None,
+    });
+}
+
+// start of a frame in 1/units of a second
+fn timestamp(recorder: &Recorder, frame: u64, units: u64) -> u64 {
+    return frame * recorder.rate.1 * units / recorder.rate.0;
+}
+
+fn write_header(recorder: &mut Recorder) -> std::io::Result<()> {
+    let mut out = Vec::new();
+    match recorder.format {
+        Format::Gif => gif::write_header(&mut out, recorder.width, recorder.height),
+        Format::Apng => {
+            png::write_header(&mut out, recorder.width, recorder.height);
+            // the frame count is patched in by finish
+            png::write_animation_control(&mut out, 0, 0);
+        }
+        Format::Y4m => y4m::write_header(&mut out, recorder.width, recorder.height, recorder.rate),
+    }
+    return recorder.out.write_all(&out);
+}
+
+fn write_gif_frame(recorder: &mut Recorder, rgba: &[u8], delay: u64) -> std::io::Result<()> {
+    let mut out = Vec::new();
+    gif::write_frame(&mut out, rgba, recorder.width, recorder.height, delay as u16);
+    recorder.written += 1;
+    return recorder.out.write_all(&out);
+}
+
+// the frames must all have the size of the first one
+pub fn add_frame(recorder: &mut Recorder, rgba: &[u8], width: usize, height: usize) -> std::io::Result<()> {
+    if recorder.frames == 0 {
+        recorder.width = width;
+        recorder.height = height;
+        write_header(recorder)?;
+    } else if width != recorder.width || height != recorder.height {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("frame is {}x{}, the recording {}x{}", width, height, recorder.width, recorder.height)
+            ));
+    }
+    let frame = recorder.frames;
+    recorder.frames += 1;
+
+    match recorder.format {
+        Format::Gif => {
+            // frames that start less than the minimum delay after the pending one are dropped
+            let start = timestamp(recorder, frame, 100);
+            let pending = recorder.pending.take();
+            match pending {
+                Some((pending_rgba, pending_start)) if start - pending_start < GIF_MIN_DELAY => {
+                    recorder.pending = Some((pending_rgba, pending_start));
+                }
+                Some((pending_rgba, pending_start)) => {
+                    write_gif_frame(recorder, &pending_rgba, start - pending_start)?;
+                    recorder.pending = Some((rgba.to_vec(), start));
+                }
+                None => {
+                    recorder.pending = Some((rgba.to_vec(), start));
+                }
+            }
+        }
+        Format::Apng => {
+            let delay = timestamp(recorder, frame + 1, 1000) - timestamp(recorder, frame, 1000);
+            let image_data = png::encode_image_data(rgba, width, height);
+            let mut out = Vec::new();
+            png::write_frame_control(&mut out, recorder.sequence, width, height, delay as u16, 1000);
+            recorder.sequence += 1;
+            if frame == 0 {
+                png::write_chunk(&mut out, b"IDAT", &image_data);
+            } else {
+                png::write_frame_data(&mut out, recorder.sequence, &image_data);
+                recorder.sequence += 1;
+            }
+            recorder.out.write_all(&out)?;
+        }
+        Format::Y4m => {
+            let mut out = Vec::with_capacity(width * height * 3 / 2 + 6);
+            y4m::write_frame(&mut out, rgba, width, height);
+            recorder.out.write_all(&out)?;
+        }
+    }
+    return Ok(());
+}
+
+// writes what is still pending and the trailer, an empty recording leaves an empty file
+pub fn finish(mut recorder: Recorder) -> std::io::Result<()> {
+    if recorder.frames > 0 {
+        match recorder.format {
+            Format::Gif => {
+                if let Some((rgba, start)) = recorder.pending.take() {
+                    let end = timestamp(&recorder, recorder.frames, 100);
+                    write_gif_frame(&mut recorder, &rgba, (end - start).max(GIF_MIN_DELAY))?;
+                }
+                let mut out = Vec::new();
+                gif::write_trailer(&mut out);
+                recorder.out.write_all(&out)?;
+            }
+            Format::Apng => {
+                let mut out = Vec::new();
+                png::write_chunk(&mut out, b"IEND", &[]);
+                recorder.out.write_all(&out)?;
+                let mut control = Vec::new();
+                png::write_animation_control(&mut control, recorder.frames as u32, 0);
+                recorder.out.seek(SeekFrom::Start(APNG_ANIMATION_CONTROL_OFFSET))?;
+                recorder.out.write_all(&control)?;
+            }
+            Format::Y4m => {}
+        }
+    }
+    return recorder.out.flush();
+}
+
+pub struct WavRecorder {
+    out: BufWriter<File>,
+    sample_rate: u32,
+    samples: u32,
+}
+
+pub fn new_wav_recorder(path: &str, sample_rate: u32) -> std::io::Result<WavRecorder> {
+    let mut recorder = WavRecorder {
+        out: BufWriter::new(File::create(path)?),
+        sample_rate: sample_rate,
+        samples: 0,
+    };
+    let mut header = Vec::with_capacity(wav::HEADER_SIZE);
+    wav::write_header(&mut header, sample_rate, 0);
+    recorder.out.write_all(&header)?;
+    return Ok(recorder);
+}
+
+// the apu produces the samples of each frame along with it, so adding them
+// every frame keeps the audio in step with the video
+pub fn add_samples(recorder: &mut WavRecorder, samples: &[f32]) -> std::io::Result<()> {
+    let mut out = Vec::with_capacity(samples.len() * 2);
+    wav::write_samples(&mut out, samples);
+    recorder.samples += samples.len() as u32;
+    return recorder.out.write_all(&out);
+}
+
+pub fn finish_wav(mut recorder: WavRecorder) -> std::io::Result<()> {
+    let mut header = Vec::with_capacity(wav::HEADER_SIZE);
+    wav::write_header(&mut header, recorder.sample_rate, recorder.samples);
+    recorder.out.seek(SeekFrom::Start(0))?;
+    recorder.out.write_all(&header)?;
+    return recorder.out.flush();
+}
@@ -0,0 +1,267 @@
+// GIF89a encoder, every frame carries its own colour table so the few NES
+// colours on screen are stored exactly
+use std::collections::HashMap;
+
+const MAX_CODES: u16 = 4096;
+
+// header, a logical screen without a global colour table and the
+// NETSCAPE2.0 extension that loops the animation forever
+pub fn write_header(out: &mut Vec<u8>, width: usize, height: usize) {
+    out.extend_from_slice(b"GIF89a");
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    // no global colour table, background colour, square pixels
+    out.extend_from_slice(&[0x00, 0x00, 0x00]);
+    out.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+    out.extend_from_slice(b"NETSCAPE2.0");
+    out.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+}
+
+pub fn write_trailer(out: &mut Vec<u8>) {
+    out.push(0x3B);
+}
+
+// colours of the image and the index of every pixel into them, images with
+// more than 256 colours (the ntsc filter) fall back to a 6x7x6 colour cube
+fn index_colours(rgba: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut colours = Vec::new();
+    let mut lookup: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+    for pixel in rgba.chunks(4) {
+        let colour = [pixel[0], pixel[1], pixel[2]];
+        if let Some(index) = lookup.get(&colour) {
+            indices.push(*index);
+            continue;
+        }
+        if lookup.len() == 256 {
+            return cube_colours(rgba);
+        }
+        lookup.insert(colour, lookup.len() as u8);
+        indices.push((lookup.len() - 1) as u8);
+        colours.extend_from_slice(&colour);
+    }
+    return (colours, indices);
+}
+
+fn cube_colours(rgba: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut colours = Vec::with_capacity(252 * 3);
+    for r in 0..6 {
+        for g in 0..7 {
+            for b in 0..6 {
+                colours.push((r * 255 / 5) as u8);
+                colours.push((g * 255 / 6) as u8);
+                colours.push((b * 255 / 5) as u8);
+            }
+        }
+    }
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+    for pixel in rgba.chunks(4) {
+        let r = ((pixel[0] as u32) * 5 + 127) / 255;
+        let g = ((pixel[1] as u32) * 6 + 127) / 255;
+        let b = ((pixel[2] as u32) * 5 + 127) / 255;
+        indices.push((r * 42 + g * 6 + b) as u8);
+    }
+    return (colours, indices);
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+fn write_bits(writer: &mut BitWriter, value: u16, length: u32) {
+    writer.bits = writer.bits | ((value as u32) << writer.count);
+    writer.count += length;
+    while writer.count >= 8 {
+        writer.out.push(writer.bits as u8);
+        writer.bits = writer.bits >> 8;
+        writer.count -= 8;
+    }
+}
+
+// variable length LZW codes, least significant bit first
+fn lzw_compress(indices: &[u8], min_code_size: u32) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut writer = BitWriter { out: Vec::new(), bits: 0, count: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut code_size = min_code_size + 1;
+
+    write_bits(&mut writer, clear, code_size);
+    let mut prefix = indices[0] as u16;
+    for &index in &indices[1..] {
+        if let Some(code) = table.get(&(prefix, index)) {
+            prefix = *code;
+            continue;
+        }
+        write_bits(&mut writer, prefix, code_size);
+        if next < MAX_CODES {
+            table.insert((prefix, index), next);
+            // the decoder adds its entries one code later, so widen after the code that needs it
+            if next == (1 << code_size) && code_size < 12 {
+                code_size += 1;
+            }
+            next += 1;
+        } else {
+            write_bits(&mut writer, clear, code_size);
+            table.clear();
+            next = end + 1;
+            code_size = min_code_size + 1;
+        }
+        prefix = index as u16;
+    }
+    write_bits(&mut writer, prefix, code_size);
+    write_bits(&mut writer, end, code_size);
+    if writer.count > 0 {
+        writer.out.push(writer.bits as u8);
+    }
+    return writer.out;
+}
+
+// an image shown for delay hundredths of a second
+pub fn write_frame(out: &mut Vec<u8>, rgba: &[u8], width: usize, height: usize, delay: u16) {
+    let (mut colours, indices) = index_colours(rgba);
+    // the colour table holds a power of two of at least 2 entries
+    let mut table_bits = 1;
+    while (1 << table_bits) * 3 < colours.len() {
+        table_bits += 1;
+    }
+    colours.resize((1 << table_bits) * 3, 0);
+
+    // graphic control extension, no disposal and no transparency
+    out.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+    out.extend_from_slice(&delay.to_le_bytes());
+    out.extend_from_slice(&[0x00, 0x00]);
+
+    // image descriptor at 0,0 with a local colour table
+    out.push(0x2C);
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    out.push(0x80 | (table_bits - 1) as u8);
+    out.extend_from_slice(&colours);
+
+    let min_code_size = (table_bits as u32).max(2);
+    out.push(min_code_size as u8);
+    for block in lzw_compress(&indices, min_code_size).chunks(255) {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0x00);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a plain GIF LZW decoder, growing the code size after the entry that fills it
+    fn lzw_decompress(data: &[u8], min_code_size: u32) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+        let mut table: Vec<Vec<u8>> = (0..clear).map(|index| vec![index as u8]).collect();
+        table.push(Vec::new());
+        table.push(Vec::new());
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut out = Vec::new();
+        let mut position = 0;
+        loop {
+            let mut code = 0;
+            for i in 0..code_size {
+                let bit = (data[position / 8] >> (position % 8)) & 1;
+                code = code | ((bit as usize) << i);
+                position += 1;
+            }
+            if code == clear {
+                table.truncate(end + 1);
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                break;
+            }
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                // the code being defined right now, previous plus its own first index
+                (None, Some(previous)) if code == table.len() => {
+                    let mut entry = previous.clone();
+                    entry.push(previous[0]);
+                    entry
+                }
+                _ => panic!("code {} before it was defined", code),
+            };
+            out.extend_from_slice(&entry);
+            if let Some(previous) = previous {
+                if table.len() < MAX_CODES as usize {
+                    let mut added = previous;
+                    added.push(entry[0]);
+                    table.push(added);
+                }
+            }
+            if table.len() == (1 << code_size) && code_size < 12 {
+                code_size += 1;
+            }
+            previous = Some(entry);
+        }
+        return out;
+    }
+
+    fn noise(count: usize, colours: u32) -> Vec<u8> {
+        let mut seed = 1u32;
+        return (0..count).map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            return ((seed >> 16) % colours) as u8;
+        }).collect();
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        // repeats that build long strings, and noise that fills the table and clears it
+        let runs: Vec<u8> = (0..20000).map(|i| ((i / 7) % 3) as u8).collect();
+        assert_eq!(lzw_decompress(&lzw_compress(&runs, 2), 2), runs);
+        let indices = noise(61440, 256);
+        assert_eq!(lzw_decompress(&lzw_compress(&indices, 8), 8), indices);
+        let indices = noise(61440, 5);
+        assert_eq!(lzw_decompress(&lzw_compress(&indices, 3), 3), indices);
+        assert_eq!(lzw_decompress(&lzw_compress(&[1], 2), 2), vec![1]);
+    }
+
+    #[test]
+    fn frame_pixels_round_trip() {
+        let (width, height) = (32, 24);
+        let rgba: Vec<u8> = noise(width * height, 13).iter().flat_map(|index| vec![index * 17, 0x80, 0xFF - index, 0xFF]).collect();
+        let mut out = Vec::new();
+        write_frame(&mut out, &rgba, width, height, 2);
+        assert_eq!(&out[..8], &[0x21, 0xF9, 0x04, 0x00, 0x02, 0x00, 0x00, 0x00]);
+        assert_eq!(out[8], 0x2C);
+        // 13 colours need a table of 16
+        assert_eq!(out[17], 0x80 | 3);
+        let colours = &out[18..18 + 16 * 3];
+        let min_code_size = out[18 + 16 * 3] as u32;
+        let mut data = Vec::new();
+        let mut position = 18 + 16 * 3 + 1;
+        while out[position] != 0 {
+            let length = out[position] as usize;
+            data.extend_from_slice(&out[position + 1..position + 1 + length]);
+            position += 1 + length;
+        }
+        assert_eq!(position, out.len() - 1);
+        let indices = lzw_decompress(&data, min_code_size);
+        assert_eq!(indices.len(), width * height);
+        for (pixel, index) in rgba.chunks(4).zip(indices) {
+            let colour = &colours[index as usize * 3..index as usize * 3 + 3];
+            assert_eq!(colour, &pixel[..3]);
+        }
+    }
+
+    #[test]
+    fn many_colours_fall_back_to_the_cube() {
+        let rgba: Vec<u8> = (0..300u32).flat_map(|i| vec![i as u8, (i >> 8) as u8, 0, 0xFF]).collect();
+        let (colours, indices) = index_colours(&rgba);
+        assert_eq!(colours.len(), 252 * 3);
+        assert_eq!(&colours[indices[255] as usize * 3..indices[255] as usize * 3 + 3], &[255, 0, 0]);
+    }
+}
@@ -0,0 +1,137 @@
+// controller input played back one line per frame, a field of 8 characters
+// for each port in the order RLDUTSBA with . for a released button and a
+// lower case a or b for A or B held as a turbo button:
+//
+//     turbo 4 2
+//     ...T.... ........
+//     R......a
+//
+// a turbo line sets the turbo period and pressed frames from the next frame
+// on, lines starting with # are comments, missing ports and frames after
+// the last line have nothing pressed. the page saves what was played since
+// power on in this form
+use super::super::nes::input;
+
+// the button of each position of a field
+const BUTTON_LETTERS: [char; 8] = ['R', 'L', 'D', 'U', 'T', 'S', 'B', 'A'];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Frame {
+    pub buttons: [u8; input::PORTS],
+    pub turbo: [u8; input::PORTS],
+    // period and pressed frames set before this frame
+    pub turbo_rate: Option<(u8, u8)>,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Frame>, String> {
+    let mut frames = Vec::new();
+    let mut turbo_rate = None;
+    for (index, line) in text.lines().enumerate() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first() == Some(&"turbo") {
+            let rate: Vec<u8> = fields[1..].iter().filter_map(|field| field.parse().ok()).collect();
+            if rate.len() != 2 || fields.len() != 3 {
+                return Err(format!("line {}: expected turbo <period> <pressed frames>", index + 1));
+            }
+            if rate[0] < 2 || rate[1] < 1 || rate[1] >= rate[0] {
+                return Err(format!("line {}: turbo needs a period of at least 2 and 1 to period - 1 pressed frames", index + 1));
+            }
+            turbo_rate = Some((rate[0], rate[1]));
+            continue;
+        }
+        if fields.len() > input::PORTS {
+            return Err(format!("line {}: more than {} ports", index + 1, input::PORTS));
+        }
+        let mut frame = Frame { buttons: [0; input::PORTS], turbo: [0; input::PORTS], turbo_rate: turbo_rate.take() };
+        for (port, field) in fields.iter().enumerate() {
+            if field.chars().count() != 8 {
+                return Err(format!("line {}: {} is not 8 buttons", index + 1, field));
+            }
+            for (position, c) in field.chars().enumerate() {
+                let bit = 0x80 >> position;
+                if (c == 'a' && bit == input::BUTTON_A) || (c == 'b' && bit == input::BUTTON_B) {
+                    frame.turbo[port] = frame.turbo[port] | bit;
+                } else if c != '.' {
+                    frame.buttons[port] = frame.buttons[port] | bit;
+                }
+            }
+        }
+        frames.push(frame);
+    }
+    return Ok(frames);
+}
+
+// the text parse reads back, with a field for every port
+pub fn format(frames: &[Frame]) -> String {
+    let mut text = String::new();
+    for frame in frames {
+        if let Some((period, on_frames)) = frame.turbo_rate {
+            text.push_str(&format!("turbo {} {}\n", period, on_frames));
+        }
+        let fields: Vec<String> = (0..input::PORTS).map(|port| format_field(frame.buttons[port], frame.turbo[port])).collect();
+        text.push_str(&fields.join(" "));
+        text.push('\n');
+    }
+    return text;
+}
+
+fn format_field(buttons: u8, turbo: u8) -> String {
+    let mut field = String::new();
+    for (position, letter) in BUTTON_LETTERS.iter().enumerate() {
+        let bit = 0x80 >> position;
+        if (turbo & bit) != 0 {
+            field.push(letter.to_ascii_lowercase());
+        } else if (buttons & bit) != 0 {
+            field.push(*letter);
+        } else {
+            field.push('.');
+        }
+    }
+    return field;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_map_to_button_bits() {
+        let frames = parse("# a comment\nR......A ....T...\n\n.L...S..\n").unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].buttons, [input::BUTTON_RIGHT | input::BUTTON_A, input::BUTTON_START]);
+        // an empty line is a frame with nothing pressed
+        assert_eq!(frames[1].buttons, [0, 0]);
+        assert_eq!(frames[2].buttons, [input::BUTTON_LEFT | input::BUTTON_SELECT, 0]);
+    }
+
+    #[test]
+    fn bad_lines_are_reported_by_number() {
+        assert_eq!(parse("........\nR.....A\n"), Err("line 2: R.....A is not 8 buttons".to_string()));
+        assert_eq!(parse("........ ........ ........\n"), Err("line 1: more than 2 ports".to_string()));
+    }
+
+    #[test]
+    fn turbo_lines_outside_the_rate_limits_are_rejected() {
+        let error = Err("line 2: turbo needs a period of at least 2 and 1 to period - 1 pressed frames".to_string());
+        assert_eq!(parse("........\nturbo 1 1\n"), error);
+        assert_eq!(parse("........\nturbo 4 0\n"), error);
+        assert_eq!(parse("........\nturbo 4 4\n"), error);
+        assert_eq!(parse("turbo 4\n").unwrap_err(), "line 1: expected turbo <period> <pressed frames>");
+        assert_eq!(parse("turbo 2 1\n.......a\n").unwrap()[0].turbo_rate, Some((2, 1)));
+    }
+
+    #[test]
+    fn formatted_frames_parse_back() {
+        let frames = vec![
+            Frame { buttons: [0, 0], turbo: [0, 0], turbo_rate: Some((4, 2)) },
+            Frame { buttons: [input::BUTTON_UP | input::BUTTON_B, input::BUTTON_SELECT], turbo: [input::BUTTON_A, 0], turbo_rate: None },
+            Frame { buttons: [0, input::BUTTON_RIGHT], turbo: [0, input::BUTTON_A | input::BUTTON_B], turbo_rate: Some((6, 1)) },
+        ];
+        let text = format(&frames);
+        assert_eq!(text, "turbo 4 2\n........ ........\n...U..Ba .....S..\nturbo 6 1\n........ R.....ba\n");
+        assert_eq!(parse(&text), Ok(frames));
+    }
+}
@@ -0,0 +1,30 @@
+// 16 bit mono PCM WAV
+
+pub const HEADER_SIZE: usize = 44;
+
+// the sizes are filled in by the writer once the sample count is known
+pub fn write_header(out: &mut Vec<u8>, sample_rate: u32, samples: u32) {
+    let data_size = samples * 2;
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_size).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, 1 channel
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    // bytes per second, bytes per sample, bits per sample
+    out.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_size.to_le_bytes());
+}
+
+pub fn write_samples(out: &mut Vec<u8>, samples: &[f32]) {
+    for sample in samples {
+        let value = (sample.max(-1.0).min(1.0) * 32767.0).round() as i16;
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
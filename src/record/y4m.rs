@@ -0,0 +1,43 @@
+// YUV4MPEG2 stream, uncompressed 4:2:0 frames that ffmpeg and x264 read from a pipe or file
+
+// rate is frames per second as numerator and denominator
+pub fn write_header(out: &mut Vec<u8>, width: usize, height: usize, rate: (u64, u64)) {
+    let header = format!("YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg\n", width, height, rate.0, rate.1);
+    out.extend_from_slice(header.as_bytes());
+}
+
+// BT.601 in the 16-235 studio range
+fn to_yuv(pixel: &[u8]) -> (f32, f32, f32) {
+    let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let u = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let v = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    return (y, u, v);
+}
+
+// the chroma planes average 2x2 blocks, rounded up at odd sizes
+pub fn write_frame(out: &mut Vec<u8>, rgba: &[u8], width: usize, height: usize) {
+    let chroma_width = (width + 1) / 2;
+    let chroma_height = (height + 1) / 2;
+    let mut u_sum = vec![0.0f32; chroma_width * chroma_height];
+    let mut v_sum = vec![0.0f32; chroma_width * chroma_height];
+    let mut weight = vec![0.0f32; chroma_width * chroma_height];
+
+    out.extend_from_slice(b"FRAME\n");
+    for y in 0..height {
+        for x in 0..width {
+            let (luma, u, v) = to_yuv(&rgba[(y * width + x) * 4..]);
+            out.push(luma.round() as u8);
+            let chroma = (y / 2) * chroma_width + x / 2;
+            u_sum[chroma] += u;
+            v_sum[chroma] += v;
+            weight[chroma] += 1.0;
+        }
+    }
+    for i in 0..u_sum.len() {
+        out.push((u_sum[i] / weight[i]).round() as u8);
+    }
+    for i in 0..v_sum.len() {
+        out.push((v_sum[i] / weight[i]).round() as u8);
+    }
+}
@@ -0,0 +1,6 @@
+// post-processing of the 256x240 colour index framebuffer, done on the cpu so
+// that it runs the same in the browser and in the headless runner
+pub mod ntsc;
+pub mod scale;
+pub mod display;
+pub mod png;
@@ -0,0 +1,296 @@
+use super::super::nes::ppu::palette;
+use super::super::nes::region;
+use super::ntsc;
+use super::scale;
+use super::png;
+
+// lines and columns hidden at each edge, in NES pixels
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AspectRatio {
+    // one framebuffer pixel per output pixel
+    Square,
+    // NTSC TVs show the dots 8/7 wider than they are high
+    PixelAspect8x7,
+}
+
+// everything between the ppu framebuffer and the image on screen or in a screenshot
+pub struct Display {
+    // 512 RGB entries indexed by the 9 bit colour of the framebuffer
+    palette: Vec<u8>,
+    // replaces the palette lookup when enabled
+    ntsc_filter: Option<ntsc::NtscFilter>,
+    // last set by set_ntsc_parameters, the filter starts from them
+    ntsc_parameters: palette::NtscParameters,
+    scaler: scale::Scaler,
+    overscan: Overscan,
+    aspect: AspectRatio,
+    integer_scale: usize,
+}
+
+pub fn new_display() -> Display {
+    return Display {
+        palette: palette::new_emphasis_table(&palette::PALETTE_TABLE),
+        ntsc_filter: None,
+        ntsc_parameters: palette::new_ntsc_parameters(),
+        scaler: scale::Scaler::None,
+        overscan: default_overscan(region::Region::Ntsc),
+        aspect: AspectRatio::Square,
+        integer_scale: 1,
+    };
+}
+
+// NTSC sets hid about 8 lines at the top and bottom, the 2C07 itself blanks
+// the first line and the two outer columns on each side
+pub fn default_overscan(region: region::Region) -> Overscan {
+    match region {
+        region::Region::Ntsc => return Overscan { top: 8, bottom: 8, left: 0, right: 0 },
+        region::Region::Pal | region::Region::Dendy => return Overscan { top: 1, bottom: 0, left: 2, right: 2 },
+    }
+}
+
+pub fn set_region(display: &mut Display, region: region::Region) {
+    display.palette = palette::new_emphasis_table(palette::default_palette(region));
+    display.overscan = default_overscan(region);
+}
+
+pub fn set_palette(display: &mut Display, table: Vec<u8>) {
+    display.palette = table;
+}
+
+pub fn set_ntsc_parameters(display: &mut Display, params: palette::NtscParameters) {
+    display.palette = palette::generate_ntsc_palette(&params);
+    display.ntsc_parameters = params;
+    if let Some(filter) = display.ntsc_filter.as_mut() {
+        ntsc::set_parameters(filter, params);
+    }
+}
+
+// 2 or 3 output pixels per dot, 0 turns the filter off
+pub fn set_ntsc_filter(display: &mut Display, scale: usize) {
+    if scale == 0 {
+        display.ntsc_filter = None;
+        return;
+    }
+    let mut filter = ntsc::new_ntsc_filter(scale);
+    ntsc::set_parameters(&mut filter, display.ntsc_parameters);
+    display.ntsc_filter = Some(filter);
+}
+
+pub fn set_scaler(display: &mut Display, scaler: scale::Scaler) {
+    display.scaler = scaler;
+}
+
+pub fn set_overscan(display: &mut Display, overscan: Overscan) {
+    display.overscan = overscan;
+}
+
+pub fn set_aspect_ratio(display: &mut Display, aspect: AspectRatio) {
+    display.aspect = aspect;
+}
+
+pub fn set_integer_scale(display: &mut Display, scale: usize) {
+    display.integer_scale = scale.max(1);
+}
+
+// the palette lookup alone, 256x240 with nothing cropped or filtered
+pub fn render_raw(display: &Display, frame: &[u16]) -> Vec<u8> {
+    let mut rgba = vec![0; 256 * 240 * 4];
+    palette::to_rgba(&display.palette, frame, &mut rgba);
+    return rgba;
+}
+
+// crc32 of the colour indices, independent of palette and filters
+pub fn frame_hash(frame: &[u16]) -> u32 {
+    let mut crc = 0;
+    for index in frame {
+        crc = png::crc32_update(crc, &index.to_le_bytes());
+    }
+    return crc;
+}
+
+// the colour indices as little endian bytes, the hash is their crc32
+pub fn frame_to_bytes(frame: &[u16]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(frame.len() * 2);
+    for index in frame {
+        bytes.extend_from_slice(&index.to_le_bytes());
+    }
+    return bytes;
+}
+
+pub fn frame_from_bytes(bytes: &[u8]) -> Option<Vec<u16>> {
+    if bytes.len() != 256 * 240 * 2 {
+        return None;
+    }
+    return Some(bytes.chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect());
+}
+
+// the expected frame, the actual one and where they differ side by side, the
+// third image is a dimmed grey copy of the expected frame with the changed pixels in magenta
+pub fn diff_image(display: &Display, expected: &[u16], actual: &[u16]) -> (Vec<u8>, usize, usize) {
+    let width = 256 * 3;
+    let expected_rgba = render_raw(display, expected);
+    let actual_rgba = render_raw(display, actual);
+    let mut rgba = vec![0xFF; width * 240 * 4];
+    for y in 0..240 {
+        for x in 0..256 {
+            let src = (y * 256 + x) * 4;
+            let row = y * width;
+            rgba[(row + x) * 4..(row + x) * 4 + 3].copy_from_slice(&expected_rgba[src..src + 3]);
+            rgba[(row + 256 + x) * 4..(row + 256 + x) * 4 + 3].copy_from_slice(&actual_rgba[src..src + 3]);
+            let diff = &mut rgba[(row + 512 + x) * 4..(row + 512 + x) * 4 + 3];
+            if expected[y * 256 + x] != actual[y * 256 + x] {
+                diff.copy_from_slice(&[0xFF, 0x00, 0xFF]);
+            } else {
+                let luma = (expected_rgba[src] as u32 * 299 + expected_rgba[src + 1] as u32 * 587 + expected_rgba[src + 2] as u32 * 114) / 1000;
+                let dimmed = (luma / 4) as u8;
+                diff.copy_from_slice(&[dimmed, dimmed, dimmed]);
+            }
+        }
+    }
+    return (rgba, width, 240);
+}
+
+// PNG of a frame, either raw or as displayed, tagged with the ROM name, frame number and frame hash
+pub fn screenshot(display: &mut Display, frame: &[u16], colour_phase: u32, raw: bool, rom_name: &str, frame_number: u64) -> Vec<u8> {
+    let (rgba, width, height) = if raw {
+        (render_raw(display, frame), 256, 240)
+    } else {
+        render(display, frame, colour_phase)
+    };
+    let text = [
+        ("ROM", rom_name.to_string()),
+        ("Frame", frame_number.to_string()),
+        ("Hash", format!("{:08x}", frame_hash(frame))),
+    ];
+    return png::encode_png(&rgba, width, height, &text);
+}
+
+// removes the overscan from an image that shows the whole 256x240 picture at any size
+fn crop(rgba: &[u8], width: usize, height: usize, overscan: &Overscan) -> (Vec<u8>, usize, usize) {
+    let left = overscan.left * width / 256;
+    let right = overscan.right * width / 256;
+    let top = overscan.top * height / 240;
+    let bottom = overscan.bottom * height / 240;
+    if left + right >= width || top + bottom >= height {
+        return (rgba.to_vec(), width, height);
+    }
+    let out_width = width - left - right;
+    let out_height = height - top - bottom;
+    let mut out = Vec::with_capacity(out_width * out_height * 4);
+    for y in top..(height - bottom) {
+        out.extend_from_slice(&rgba[(y * width + left) * 4..(y * width + width - right) * 4]);
+    }
+    return (out, out_width, out_height);
+}
+
+fn repeat_pixels(rgba: &[u8], width: usize, height: usize, factor: usize) -> Vec<u8> {
+    let out_width = width * factor;
+    let mut out = vec![0; out_width * height * factor * 4];
+    for y in 0..(height * factor) {
+        for x in 0..out_width {
+            let src = ((y / factor) * width + x / factor) * 4;
+            let dst = (y * out_width + x) * 4;
+            out[dst..dst + 4].copy_from_slice(&rgba[src..src + 4]);
+        }
+    }
+    return out;
+}
+
+// widens the image with linear interpolation, after the integer scale this only softens the column seams
+fn stretch_horizontal(rgba: &[u8], width: usize, height: usize, out_width: usize) -> Vec<u8> {
+    let mut out = vec![0xFF; out_width * height * 4];
+    for x in 0..out_width {
+        let position = ((x as f32) + 0.5) * (width as f32) / (out_width as f32) - 0.5;
+        let left = position.floor().max(0.0) as usize;
+        let right = (left + 1).min(width - 1);
+        let weight = (position - (left as f32)).max(0.0).min(1.0);
+        for y in 0..height {
+            for c in 0..3 {
+                let a = rgba[(y * width + left) * 4 + c] as f32;
+                let b = rgba[(y * width + right) * 4 + c] as f32;
+                out[(y * out_width + x) * 4 + c] = (a + (b - a) * weight).round() as u8;
+            }
+        }
+    }
+    return out;
+}
+
+// turns a framebuffer of 256x240 9 bit colour indices into the RGBA image to show,
+// returned with its width and height
+pub fn render(display: &mut Display, frame: &[u16], colour_phase: u32) -> (Vec<u8>, usize, usize) {
+    let (mut rgba, mut width) = match display.ntsc_filter.as_mut() {
+        Some(filter) => {
+            let width = ntsc::output_width(filter);
+            let mut rgba = vec![0; width * 240 * 4];
+            ntsc::apply(filter, frame, colour_phase, &mut rgba);
+            (rgba, width)
+        }
+        None => {
+            let mut rgba = vec![0; 256 * 240 * 4];
+            palette::to_rgba(&display.palette, frame, &mut rgba);
+            (rgba, 256)
+        }
+    };
+    let mut height = 240;
+
+    if display.scaler != scale::Scaler::None {
+        rgba = scale::apply(display.scaler, &rgba, width, height);
+        width = width * scale::factor(display.scaler);
+        height = height * scale::factor(display.scaler);
+    }
+
+    // output pixels per dot across and down, the ntsc filter makes them differ
+    let (full_width, full_height) = (width, height);
+    let (cropped, cropped_width, cropped_height) = crop(&rgba, width, height, &display.overscan);
+    rgba = cropped;
+    width = cropped_width;
+    height = cropped_height;
+
+    if display.integer_scale > 1 {
+        rgba = repeat_pixels(&rgba, width, height, display.integer_scale);
+        width = width * display.integer_scale;
+        height = height * display.integer_scale;
+    }
+
+    if display.aspect == AspectRatio::PixelAspect8x7 {
+        // make a dot 8/7 as wide as it is high
+        let out_width = width * full_height * 256 * 8 / (full_width * 240 * 7);
+        rgba = stretch_horizontal(&rgba, width, height, out_width);
+        width = out_width;
+    }
+    return (rgba, width, height);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntsc_filter_starts_from_the_set_parameters() {
+        let frame: Vec<u16> = (0..256 * 240).map(|i| ((i / 7) % 64) as u16).collect();
+        let mut params = palette::new_ntsc_parameters();
+        params.hue = 30.0;
+        params.saturation = 1.5;
+
+        let mut before = new_display();
+        set_ntsc_parameters(&mut before, params);
+        set_ntsc_filter(&mut before, 2);
+        let mut after = new_display();
+        set_ntsc_filter(&mut after, 2);
+        set_ntsc_parameters(&mut after, params);
+        let mut default = new_display();
+        set_ntsc_filter(&mut default, 2);
+
+        let image = render(&mut before, &frame, 0);
+        assert!(image == render(&mut after, &frame, 0));
+        assert!(image != render(&mut default, &frame, 0));
+    }
+}
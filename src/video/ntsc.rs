@@ -0,0 +1,111 @@
+use super::super::nes::ppu::palette;
+
+// every dot is 8 samples of the 21.477 MHz master clock, the colour subcarrier is 12 samples long
+const SAMPLES_PER_DOT: usize = 8;
+const LINE_SAMPLES: usize = 256 * SAMPLES_PER_DOT;
+// one full subcarrier cycle for luma, two for chroma so colours bleed into the neighbouring dots
+const Y_WIDTH: usize = 12;
+const IQ_WIDTH: usize = 24;
+const PADDING: usize = IQ_WIDTH / 2;
+// each line is 341 dots long, so it starts 4 samples later in the subcarrier than the previous one
+const LINE_PHASE_STEP: usize = (341 * SAMPLES_PER_DOT) % 12;
+
+pub struct NtscFilter {
+    // horizontal output pixels per dot
+    scale: usize,
+    params: palette::NtscParameters,
+    // signal level of every colour index at every subcarrier phase
+    signal_table: Vec<f32>,
+    // subcarrier of the decoder, the hue control rotates it
+    carrier_cos: Vec<f32>,
+    carrier_sin: Vec<f32>,
+    // running sums of the signal of one line, demodulated for i and q
+    sum_y: Vec<f32>,
+    sum_i: Vec<f32>,
+    sum_q: Vec<f32>,
+}
+
+pub fn new_ntsc_filter(scale: usize) -> NtscFilter {
+    let mut signal_table = vec![0.0; 512 * 12];
+    for index in 0..512 {
+        for phase in 0..12 {
+            signal_table[index * 12 + phase] = palette::ntsc_signal(index, phase) as f32;
+        }
+    }
+    let mut filter = NtscFilter {
+        scale: scale.max(2).min(3),
+        params: palette::new_ntsc_parameters(),
+        signal_table: signal_table,
+        carrier_cos: vec![0.0; 12],
+        carrier_sin: vec![0.0; 12],
+        sum_y: vec![0.0; LINE_SAMPLES + PADDING * 2 + 1],
+        sum_i: vec![0.0; LINE_SAMPLES + PADDING * 2 + 1],
+        sum_q: vec![0.0; LINE_SAMPLES + PADDING * 2 + 1],
+    };
+    update_carrier(&mut filter);
+    return filter;
+}
+
+fn update_carrier(filter: &mut NtscFilter) {
+    for phase in 0..12 {
+        let angle = std::f64::consts::PI * ((phase as f64) + palette::SIGNAL_HUE_OFFSET) / 6.0 + filter.params.hue.to_radians();
+        filter.carrier_cos[phase] = angle.cos() as f32;
+        filter.carrier_sin[phase] = angle.sin() as f32;
+    }
+}
+
+pub fn set_parameters(filter: &mut NtscFilter, params: palette::NtscParameters) {
+    filter.params = params;
+    update_carrier(filter);
+}
+
+pub fn output_width(filter: &NtscFilter) -> usize {
+    return 256 * filter.scale;
+}
+
+// modulates one line of colour indices and builds the running sums the decoder reads from,
+// the line is extended with its edge dots so the windows never run off the picture
+fn encode_line(filter: &mut NtscFilter, line: &[u16], line_phase: usize) {
+    let mut y = 0.0;
+    let mut i = 0.0;
+    let mut q = 0.0;
+    for n in 0..(LINE_SAMPLES + PADDING * 2) {
+        let position = (n as isize) - (PADDING as isize);
+        let dot = (position.max(0) as usize / SAMPLES_PER_DOT).min(255);
+        let phase = (line_phase + n + 12 - PADDING % 12) % 12;
+        let signal = filter.signal_table[((line[dot] & 0x1FF) as usize) * 12 + phase];
+        filter.sum_y[n] = y;
+        filter.sum_i[n] = i;
+        filter.sum_q[n] = q;
+        y += signal;
+        i += signal * filter.carrier_cos[phase];
+        q += signal * filter.carrier_sin[phase];
+    }
+    let end = LINE_SAMPLES + PADDING * 2;
+    filter.sum_y[end] = y;
+    filter.sum_i[end] = i;
+    filter.sum_q[end] = q;
+}
+
+// frame holds 256x240 9 bit colour indices, rgba receives output_width x 240 pixels;
+// colour_phase comes from the ppu and moves every frame, which makes the dots crawl
+pub fn apply(filter: &mut NtscFilter, frame: &[u16], colour_phase: u32, rgba: &mut [u8]) {
+    let width = output_width(filter);
+    for y in 0..240 {
+        let line_phase = ((colour_phase as usize) + y * LINE_PHASE_STEP) % 12;
+        encode_line(filter, &frame[y * 256..(y + 1) * 256], line_phase);
+        for x in 0..width {
+            // centre of the output pixel in samples
+            let centre = PADDING + (x * 2 + 1) * LINE_SAMPLES / (width * 2);
+            let luma = (filter.sum_y[centre + Y_WIDTH / 2] - filter.sum_y[centre - Y_WIDTH / 2]) / (Y_WIDTH as f32);
+            let i = (filter.sum_i[centre + IQ_WIDTH / 2] - filter.sum_i[centre - IQ_WIDTH / 2]) / (IQ_WIDTH as f32);
+            let q = (filter.sum_q[centre + IQ_WIDTH / 2] - filter.sum_q[centre - IQ_WIDTH / 2]) / (IQ_WIDTH as f32);
+            let rgb = palette::yiq_to_rgb(&filter.params, luma as f64, i as f64, q as f64);
+            let offset = (y * width + x) * 4;
+            rgba[offset] = rgb[0];
+            rgba[offset + 1] = rgb[1];
+            rgba[offset + 2] = rgb[2];
+            rgba[offset + 3] = 0xFF;
+        }
+    }
+}
@@ -0,0 +1,448 @@
+// PNG encoder with its own zlib stream: LZ77 and the fixed Huffman codes of deflate
+
+const SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+
+const WINDOW_SIZE: usize = 32768;
+const HASH_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// matches to try before settling for the longest one found
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+pub fn crc32(data: &[u8]) -> u32 {
+    return crc32_update(0, data);
+}
+
+// continues a crc over more data, start with 0
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc = crc ^ (*byte as u32);
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    return !crc;
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + (*byte as u32)) % 65521;
+        b = (b + a) % 65521;
+    }
+    return (b << 16) | a;
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+// deflate packs values starting at the least significant bit
+fn write_bits(writer: &mut BitWriter, value: u32, count: u32) {
+    writer.bits = writer.bits | (value << writer.count);
+    writer.count += count;
+    while writer.count >= 8 {
+        writer.out.push(writer.bits as u8);
+        writer.bits = writer.bits >> 8;
+        writer.count -= 8;
+    }
+}
+
+// huffman codes are stored starting at their most significant bit
+fn write_code(writer: &mut BitWriter, code: u32, length: u32) {
+    let mut reversed = 0;
+    for i in 0..length {
+        reversed = reversed | (((code >> i) & 1) << (length - 1 - i));
+    }
+    write_bits(writer, reversed, length);
+}
+
+fn write_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => write_code(writer, 0x30 + symbol, 8),
+        144..=255 => write_code(writer, 0x190 + symbol - 144, 9),
+        256..=279 => write_code(writer, symbol - 256, 7),
+        _ => write_code(writer, 0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE.iter().rposition(|base| (*base as usize) <= length).unwrap();
+    write_literal(writer, 257 + code as u32);
+    write_bits(writer, (length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+    let code = DISTANCE_BASE.iter().rposition(|base| (*base as usize) <= distance).unwrap();
+    write_code(writer, code as u32, 5);
+    write_bits(writer, (distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+}
+
+fn hash(data: &[u8], position: usize) -> usize {
+    let value = ((data[position] as usize) << 16) | ((data[position + 1] as usize) << 8) | (data[position + 2] as usize);
+    return (value.wrapping_mul(2654435761) >> 8) & (HASH_SIZE - 1);
+}
+
+// a single fixed huffman block
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { out: Vec::new(), bits: 0, count: 0 };
+    // final block, fixed huffman codes
+    write_bits(&mut writer, 1, 1);
+    write_bits(&mut writer, 1, 2);
+
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut previous = vec![usize::MAX; WINDOW_SIZE];
+    let mut position = 0;
+    while position < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if position + MIN_MATCH <= data.len() {
+            let h = hash(data, position);
+            let mut candidate = head[h];
+            let mut chain = 0;
+            while candidate != usize::MAX && position - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let limit = MAX_MATCH.min(data.len() - position);
+                let mut length = 0;
+                while length < limit && data[candidate + length] == data[position + length] {
+                    length += 1;
+                }
+                if length > best_length {
+                    best_length = length;
+                    best_distance = position - candidate;
+                    if length == limit {
+                        break;
+                    }
+                }
+                candidate = previous[candidate % WINDOW_SIZE];
+                chain += 1;
+            }
+        }
+
+        let advance = if best_length >= MIN_MATCH {
+            write_match(&mut writer, best_length, best_distance);
+            best_length
+        } else {
+            write_literal(&mut writer, data[position] as u32);
+            1
+        };
+        for p in position..(position + advance) {
+            if p + MIN_MATCH <= data.len() {
+                let h = hash(data, p);
+                previous[p % WINDOW_SIZE] = head[h];
+                head[h] = p;
+            }
+        }
+        position += advance;
+    }
+
+    write_literal(&mut writer, 256);
+    if writer.count > 0 {
+        writer.out.push(writer.bits as u8);
+    }
+    return writer.out;
+}
+
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x9C];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    return out;
+}
+
+pub fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32_update(crc32(kind), data);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// signature and IHDR of an 8 bit RGB image
+pub fn write_header(out: &mut Vec<u8>, width: usize, height: usize) {
+    out.extend_from_slice(&SIGNATURE);
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth, colour type RGB, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header);
+}
+
+pub fn write_text(out: &mut Vec<u8>, keyword: &str, text: &str) {
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    data.extend_from_slice(text.as_bytes());
+    write_chunk(out, b"tEXt", &data);
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = (a as i16) + (b as i16) - (c as i16);
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        return a;
+    }
+    if pb <= pc {
+        return b;
+    }
+    return c;
+}
+
+// filters every line with the filter type that gives the smallest sum of
+// absolute differences, then compresses the result
+pub fn encode_image_data(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let stride = width * 3;
+    let mut filtered = Vec::with_capacity((stride + 1) * height);
+    let mut previous = vec![0u8; stride];
+    let mut line = vec![0u8; stride];
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    for y in 0..height {
+        for x in 0..width {
+            line[x * 3..x * 3 + 3].copy_from_slice(&rgba[(y * width + x) * 4..(y * width + x) * 4 + 3]);
+        }
+        let mut best_type = 0;
+        let mut best_sum = u64::MAX;
+        for filter_type in 0..5 {
+            for i in 0..stride {
+                let left = if i >= 3 { line[i - 3] } else { 0 };
+                let up = previous[i];
+                let up_left = if i >= 3 { previous[i - 3] } else { 0 };
+                let predictor = match filter_type {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => (((left as u16) + (up as u16)) / 2) as u8,
+                    _ => paeth(left, up, up_left),
+                };
+                candidate[i] = line[i].wrapping_sub(predictor);
+            }
+            let sum: u64 = candidate.iter().map(|v| (*v as i8 as i16).abs() as u64).sum();
+            if sum < best_sum {
+                best_sum = sum;
+                best_type = filter_type;
+                best.copy_from_slice(&candidate);
+            }
+        }
+        filtered.push(best_type as u8);
+        filtered.extend_from_slice(&best);
+        previous.copy_from_slice(&line);
+    }
+    return zlib_compress(&filtered);
+}
+
+// text holds keyword and value pairs stored as tEXt chunks
+pub fn encode_png(rgba: &[u8], width: usize, height: usize, text: &[(&str, String)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_header(&mut out, width, height);
+    for (keyword, value) in text {
+        write_text(&mut out, keyword, value);
+    }
+    write_chunk(&mut out, b"IDAT", &encode_image_data(rgba, width, height));
+    write_chunk(&mut out, b"IEND", &[]);
+    return out;
+}
+
+// acTL of an animated PNG, plays 0 loops forever
+pub fn write_animation_control(out: &mut Vec<u8>, frames: u32, plays: u32) {
+    let mut data = Vec::new();
+    data.extend_from_slice(&frames.to_be_bytes());
+    data.extend_from_slice(&plays.to_be_bytes());
+    write_chunk(out, b"acTL", &data);
+}
+
+// fcTL of a full size frame shown for delay_num/delay_den seconds
+pub fn write_frame_control(out: &mut Vec<u8>, sequence: u32, width: usize, height: usize, delay_num: u16, delay_den: u16) {
+    let mut data = Vec::new();
+    data.extend_from_slice(&sequence.to_be_bytes());
+    data.extend_from_slice(&(width as u32).to_be_bytes());
+    data.extend_from_slice(&(height as u32).to_be_bytes());
+    // x and y offset
+    data.extend_from_slice(&[0; 8]);
+    data.extend_from_slice(&delay_num.to_be_bytes());
+    data.extend_from_slice(&delay_den.to_be_bytes());
+    // no disposal, replace the previous frame
+    data.extend_from_slice(&[0, 0]);
+    write_chunk(out, b"fcTL", &data);
+}
+
+// fdAT, the IDAT of every frame after the first
+pub fn write_frame_data(out: &mut Vec<u8>, sequence: u32, image_data: &[u8]) {
+    let mut data = sequence.to_be_bytes().to_vec();
+    data.extend_from_slice(image_data);
+    write_chunk(out, b"fdAT", &data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    fn read_bits(reader: &mut BitReader, count: u32) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let bit = (reader.data[reader.position / 8] >> (reader.position % 8)) & 1;
+            value = value | ((bit as u32) << i);
+            reader.position += 1;
+        }
+        return value;
+    }
+
+    // reads a fixed huffman code one bit at a time, most significant bit first
+    fn read_literal(reader: &mut BitReader) -> u32 {
+        let mut code = 0;
+        for length in 1..=9 {
+            code = (code << 1) | read_bits(reader, 1);
+            match (length, code) {
+                (7, 0..=0x17) => return 256 + code,
+                (8, 0x30..=0xBF) => return code - 0x30,
+                (8, 0xC0..=0xC7) => return 280 + code - 0xC0,
+                (9, 0x190..=0x1FF) => return 144 + code - 0x190,
+                _ => {}
+            }
+        }
+        panic!("bad literal code {:X}", code);
+    }
+
+    // just enough of inflate for the single fixed block the encoder writes
+    fn zlib_decompress(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(&stream[..2], &[0x78, 0x9C]);
+        let mut reader = BitReader { data: &stream[2..stream.len() - 4], position: 0 };
+        assert_eq!(read_bits(&mut reader, 3), 0b011);
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let symbol = read_literal(&mut reader);
+            if symbol < 256 {
+                out.push(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                break;
+            }
+            let code = (symbol - 257) as usize;
+            let length = LENGTH_BASE[code] as usize + read_bits(&mut reader, LENGTH_EXTRA[code] as u32) as usize;
+            let mut code = 0;
+            for _ in 0..5 {
+                code = (code << 1) | read_bits(&mut reader, 1) as usize;
+            }
+            let distance = DISTANCE_BASE[code] as usize + read_bits(&mut reader, DISTANCE_EXTRA[code] as u32) as usize;
+            for _ in 0..length {
+                out.push(out[out.len() - distance]);
+            }
+        }
+        assert_eq!(&stream[stream.len() - 4..], &adler32(&out).to_be_bytes());
+        return out;
+    }
+
+    // a picture with flat areas, gradients and noise so every filter gets picked
+    fn test_image(width: usize, height: usize) -> Vec<u8> {
+        let mut rgba = Vec::new();
+        let mut seed = 12345u32;
+        for y in 0..height {
+            for x in 0..width {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let noise = (seed >> 16) as u8;
+                let pixel = match (x / 16 + y / 16) % 3 {
+                    0 => [0x20, 0x40, 0x80],
+                    1 => [(x * 4) as u8, (y * 3) as u8, (x + y) as u8],
+                    _ => [noise, noise / 2, 0xFF - noise],
+                };
+                rgba.extend_from_slice(&pixel);
+                rgba.push(0xFF);
+            }
+        }
+        return rgba;
+    }
+
+    #[test]
+    fn checksums_match_the_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32_update(crc32(b"1234"), b"56789"), 0xCBF43926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn deflate_round_trips() {
+        let mut data = b"abcabcabcabc".to_vec();
+        // a run longer than the longest match and matches from far back
+        data.extend_from_slice(&[7; 1000]);
+        let noise: Vec<u8> = (0..40000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        data.extend_from_slice(&noise);
+        data.extend_from_slice(&noise[..5000]);
+        let compressed = zlib_compress(&data);
+        assert_eq!(zlib_decompress(&compressed), data);
+        assert_eq!(zlib_decompress(&zlib_compress(&[])), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn png_pixels_round_trip() {
+        let (width, height) = (48, 40);
+        let rgba = test_image(width, height);
+        let png = encode_png(&rgba, width, height, &[("Title", "test".to_string())]);
+        assert_eq!(&png[..8], &SIGNATURE);
+
+        let mut chunks = Vec::new();
+        let mut position = 8;
+        while position < png.len() {
+            let length = u32::from_be_bytes([png[position], png[position + 1], png[position + 2], png[position + 3]]) as usize;
+            let kind = &png[position + 4..position + 8];
+            let data = &png[position + 8..position + 8 + length];
+            let crc = &png[position + 8 + length..position + 12 + length];
+            assert_eq!(crc, &crc32_update(crc32(kind), data).to_be_bytes());
+            chunks.push((kind.to_vec(), data.to_vec()));
+            position += 12 + length;
+        }
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| kind.as_slice()).collect();
+        assert_eq!(kinds, vec![&b"IHDR"[..], b"tEXt", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[1].1, b"Title\0test".to_vec());
+
+        let filtered = zlib_decompress(&chunks[2].1);
+        let stride = width * 3;
+        assert_eq!(filtered.len(), (stride + 1) * height);
+        let mut previous = vec![0u8; stride];
+        let mut types = Vec::new();
+        for y in 0..height {
+            let filter_type = filtered[y * (stride + 1)];
+            types.push(filter_type);
+            let mut line = filtered[y * (stride + 1) + 1..(y + 1) * (stride + 1)].to_vec();
+            for i in 0..stride {
+                let left = if i >= 3 { line[i - 3] } else { 0 };
+                let up_left = if i >= 3 { previous[i - 3] } else { 0 };
+                let predictor = match filter_type {
+                    0 => 0,
+                    1 => left,
+                    2 => previous[i],
+                    3 => (((left as u16) + (previous[i] as u16)) / 2) as u8,
+                    _ => paeth(left, previous[i], up_left),
+                };
+                line[i] = line[i].wrapping_add(predictor);
+            }
+            for x in 0..width {
+                assert_eq!(&line[x * 3..x * 3 + 3], &rgba[(y * width + x) * 4..(y * width + x) * 4 + 3], "pixel {},{}", x, y);
+            }
+            previous = line;
+        }
+        types.sort();
+        types.dedup();
+        assert!(types.len() > 1, "only filter {:?} was used", types);
+    }
+}
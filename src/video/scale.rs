@@ -0,0 +1,517 @@
+// pixel art upscalers working on RGBA images of any size, pixels are handled as 0x00RRGGBB
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scaler {
+    None,
+    Scale2x,
+    Scale3x,
+    // Maxim Stepin's hq2x
+    Hq2x,
+    // Hyllian's 2xBR, xBR level 2 at twice the size
+    Xbr2x,
+}
+
+pub fn factor(scaler: Scaler) -> usize {
+    match scaler {
+        Scaler::None => return 1,
+        Scaler::Scale3x => return 3,
+        Scaler::Scale2x | Scaler::Hq2x | Scaler::Xbr2x => return 2,
+    }
+}
+
+// the names the page and the headless runner select the scalers by
+pub fn from_name(name: &str) -> Option<Scaler> {
+    match name {
+        "none" => return Some(Scaler::None),
+        "scale2x" => return Some(Scaler::Scale2x),
+        "scale3x" => return Some(Scaler::Scale3x),
+        "hq2x" => return Some(Scaler::Hq2x),
+        "xbr" => return Some(Scaler::Xbr2x),
+        _ => return None,
+    }
+}
+
+fn to_pixels(rgba: &[u8]) -> Vec<u32> {
+    return rgba.chunks(4).map(|p| ((p[0] as u32) << 16) | ((p[1] as u32) << 8) | (p[2] as u32)).collect();
+}
+
+fn to_rgba(pixels: &[u32]) -> Vec<u8> {
+    let mut rgba = vec![0xFF; pixels.len() * 4];
+    for i in 0..pixels.len() {
+        rgba[i * 4] = (pixels[i] >> 16) as u8;
+        rgba[i * 4 + 1] = (pixels[i] >> 8) as u8;
+        rgba[i * 4 + 2] = pixels[i] as u8;
+    }
+    return rgba;
+}
+
+// the 5x5 neighbourhood of a pixel, clamped at the image edges
+fn neighbourhood(pixels: &[u32], width: usize, height: usize, x: usize, y: usize) -> [[u32; 5]; 5] {
+    let mut n = [[0; 5]; 5];
+    for dy in 0..5 {
+        for dx in 0..5 {
+            let sx = ((x + dx) as isize - 2).max(0).min(width as isize - 1) as usize;
+            let sy = ((y + dy) as isize - 2).max(0).min(height as isize - 1) as usize;
+            n[dy][dx] = pixels[sy * width + sx];
+        }
+    }
+    return n;
+}
+
+// rotates a neighbourhood a quarter turn counter-clockwise, so the rules written for
+// one corner of the output cover the other three corners
+fn rotate(n: &[[u32; 5]; 5]) -> [[u32; 5]; 5] {
+    let mut r = [[0; 5]; 5];
+    for y in 0..5 {
+        for x in 0..5 {
+            r[4 - x][y] = n[y][x];
+        }
+    }
+    return r;
+}
+
+// output block of a pixel, each scaler fills it from the neighbourhood
+fn scale_with(rgba: &[u8], width: usize, height: usize, scale: usize, block: &dyn Fn(&[[u32; 5]; 5], &mut [u32])) -> Vec<u8> {
+    let pixels = to_pixels(rgba);
+    let out_width = width * scale;
+    let mut out = vec![0; out_width * height * scale];
+    let mut pixel_block = vec![0; scale * scale];
+    for y in 0..height {
+        for x in 0..width {
+            let n = neighbourhood(&pixels, width, height, x, y);
+            block(&n, &mut pixel_block);
+            for by in 0..scale {
+                for bx in 0..scale {
+                    out[(y * scale + by) * out_width + x * scale + bx] = pixel_block[by * scale + bx];
+                }
+            }
+        }
+    }
+    return to_rgba(&out);
+}
+
+// returns the scaled RGBA image, factor(scaler) times wider and higher
+pub fn apply(scaler: Scaler, rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    match scaler {
+        Scaler::None => return rgba.to_vec(),
+        Scaler::Scale2x => return scale_with(rgba, width, height, 2, &scale2x_block),
+        Scaler::Scale3x => return scale_with(rgba, width, height, 3, &scale3x_block),
+        Scaler::Hq2x => {
+            let table = new_hq2x_table();
+            return scale_with(rgba, width, height, 2, &|n, out| hq2x_block(&table, n, out));
+        }
+        Scaler::Xbr2x => return scale_with(rgba, width, height, 2, &xbr2x_block),
+    }
+}
+
+// AdvMAME2x/3x, with the neighbours named
+// A B C
+// D E F
+// G H I
+fn scale2x_block(n: &[[u32; 5]; 5], out: &mut [u32]) {
+    let (b, d, e, f, h) = (n[1][2], n[2][1], n[2][2], n[2][3], n[3][2]);
+    out[0] = if d == b && b != f && d != h { d } else { e };
+    out[1] = if b == f && b != d && f != h { f } else { e };
+    out[2] = if d == h && d != b && h != f { d } else { e };
+    out[3] = if h == f && d != h && b != f { f } else { e };
+}
+
+fn scale3x_block(n: &[[u32; 5]; 5], out: &mut [u32]) {
+    let (a, b, c) = (n[1][1], n[1][2], n[1][3]);
+    let (d, e, f) = (n[2][1], n[2][2], n[2][3]);
+    let (g, h, i) = (n[3][1], n[3][2], n[3][3]);
+    let top_left = d == b && b != f && d != h;
+    let top_right = b == f && b != d && f != h;
+    let bottom_left = d == h && d != b && h != f;
+    let bottom_right = h == f && d != h && b != f;
+    out[0] = if top_left { d } else { e };
+    out[1] = if (top_left && e != c) || (top_right && e != a) { b } else { e };
+    out[2] = if top_right { f } else { e };
+    out[3] = if (top_left && e != g) || (bottom_left && e != a) { d } else { e };
+    out[4] = e;
+    out[5] = if (top_right && e != i) || (bottom_right && e != c) { f } else { e };
+    out[6] = if bottom_left { d } else { e };
+    out[7] = if (bottom_left && e != i) || (bottom_right && e != g) { h } else { e };
+    out[8] = if bottom_right { f } else { e };
+}
+
+fn yuv(pixel: u32) -> (i32, i32, i32) {
+    let r = ((pixel >> 16) & 0xFF) as i32;
+    let g = ((pixel >> 8) & 0xFF) as i32;
+    let b = (pixel & 0xFF) as i32;
+    let y = (299 * r + 587 * g + 114 * b) / 1000;
+    let u = (-169 * r - 331 * g + 500 * b) / 1000 + 128;
+    let v = (500 * r - 419 * g - 81 * b) / 1000 + 128;
+    return (y, u, v);
+}
+
+// the cheaper YUV of hqx, which its thresholds are tuned for
+fn hqx_yuv(pixel: u32) -> (i32, i32, i32) {
+    let r = ((pixel >> 16) & 0xFF) as i32;
+    let g = ((pixel >> 8) & 0xFF) as i32;
+    let b = (pixel & 0xFF) as i32;
+    return ((r + g + b) >> 2, 128 + ((r - b) >> 2), 128 + ((2 * g - r - b) >> 3));
+}
+
+// hqx treats two colours as different past these YUV thresholds
+fn is_different(a: u32, b: u32) -> bool {
+    if a == b {
+        return false;
+    }
+    let (ya, ua, va) = hqx_yuv(a);
+    let (yb, ub, vb) = hqx_yuv(b);
+    return (ya - yb).abs() > 0x30 || (ua - ub).abs() > 0x07 || (va - vb).abs() > 0x06;
+}
+
+// weighted average of three colours, the weights add up to 1 << shift
+fn blend(c1: u32, w1: u32, c2: u32, w2: u32, c3: u32, w3: u32, shift: u32) -> u32 {
+    let mut out = 0;
+    for channel in [0, 8, 16].iter() {
+        let value = (((c1 >> channel) & 0xFF) * w1 + ((c2 >> channel) & 0xFF) * w2 + ((c3 >> channel) & 0xFF) * w3) >> shift;
+        out = out | (value << channel);
+    }
+    return out;
+}
+
+// hq2x works out the top left output pixel of a block from the 3x3 neighbourhood
+// A B C
+// D E F
+// G H I
+// and gets the other three by flipping the neighbourhood
+const HQ2X_VIEWS: [[usize; 9]; 4] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8],
+    [2, 1, 0, 5, 4, 3, 8, 7, 6],
+    [6, 7, 8, 3, 4, 5, 0, 1, 2],
+    [8, 7, 6, 5, 4, 3, 2, 1, 0],
+];
+const HQ2X_B: usize = 1;
+const HQ2X_D: usize = 3;
+const HQ2X_F: usize = 5;
+const HQ2X_H: usize = 7;
+
+// the top left output pixel as a mix of E and its neighbours
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Hq2xBlend {
+    Centre,
+    // 3E + A
+    Corner,
+    // 3E + D
+    Left,
+    // 3E + B
+    Up,
+    // 2E + D + B
+    Edges,
+    // 2E + A + B
+    CornerUp,
+    // 2E + A + D
+    CornerLeft,
+    // 5E + 2B + D
+    MostlyUp,
+    // 5E + 2D + B
+    MostlyLeft,
+    // 6E + D + B
+    Soft,
+    // 14E + D + B
+    Softer,
+    // 2E + 3D + 3B
+    Strong,
+}
+
+// the hq2x rules as (mask, value) tests on the pattern of differing neighbours,
+// bit 0 A, 1 B, 2 C, 3 D, 4 F, 5 G, 6 H, 7 I. the first rule that matches gives
+// the blend, a rule with an edge only applies while the two neighbours named
+// differ from each other and falls through to the later rules otherwise
+const HQ2X_RULES: [(Option<(usize, usize)>, &[(u8, u8)], Hq2xBlend); 15] = [
+    (Some((HQ2X_B, HQ2X_F)), &[(0xBF, 0x37), (0xDB, 0x13)], Hq2xBlend::Left),
+    (Some((HQ2X_H, HQ2X_D)), &[(0xDB, 0x49), (0xEF, 0x6D)], Hq2xBlend::Up),
+    (Some((HQ2X_D, HQ2X_B)), &[(0x0B, 0x0B), (0xFE, 0x4A), (0xFE, 0x1A)], Hq2xBlend::Centre),
+    (Some((HQ2X_D, HQ2X_B)), &[
+        (0x6F, 0x2A), (0x5B, 0x0A), (0xBF, 0x3A), (0xDF, 0x5A), (0x9F, 0x8A), (0xCF, 0x8A), (0xEF, 0x4E),
+        (0x3F, 0x0E), (0xFB, 0x5A), (0xBB, 0x8A), (0x7F, 0x5A), (0xAF, 0x8A), (0xEB, 0x8A),
+    ], Hq2xBlend::Corner),
+    (None, &[(0x0B, 0x08)], Hq2xBlend::CornerUp),
+    (None, &[(0x0B, 0x02)], Hq2xBlend::CornerLeft),
+    (None, &[(0x2F, 0x2F)], Hq2xBlend::Softer),
+    (None, &[(0xBF, 0x37), (0xDB, 0x13)], Hq2xBlend::MostlyUp),
+    (None, &[(0xDB, 0x49), (0xEF, 0x6D)], Hq2xBlend::MostlyLeft),
+    (None, &[(0x1B, 0x03), (0x4F, 0x43), (0x8B, 0x83), (0x6B, 0x43)], Hq2xBlend::Left),
+    (None, &[(0x4B, 0x09), (0x8B, 0x89), (0x1F, 0x19), (0x3B, 0x19)], Hq2xBlend::Up),
+    (None, &[(0x7E, 0x2A), (0xEF, 0xAB), (0xBF, 0x8F), (0x7E, 0x0E)], Hq2xBlend::Strong),
+    (None, &[(0xFB, 0x6A), (0x6F, 0x6E), (0x3F, 0x3E), (0xFB, 0xFA), (0xDF, 0xDE), (0xDF, 0x1E)], Hq2xBlend::Corner),
+    (None, &[
+        (0x0A, 0x00), (0x4F, 0x4B), (0x9F, 0x1B), (0x2F, 0x0B), (0xBE, 0x0A), (0xEE, 0x0A), (0x7E, 0x0A),
+        (0xEB, 0x4B), (0x3B, 0x1B),
+    ], Hq2xBlend::Edges),
+    (None, &[(0x00, 0x00)], Hq2xBlend::Soft),
+];
+
+// one of the 256 cases: the blend while the edge neighbours differ, and the blend otherwise
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Hq2xCase {
+    edge: Option<(usize, usize)>,
+    sharp: Hq2xBlend,
+    smooth: Hq2xBlend,
+}
+
+// the rules never give one pattern two different edges, so each case needs at most one test
+fn new_hq2x_table() -> Vec<Hq2xCase> {
+    let mut table = Vec::with_capacity(256);
+    for pattern in 0..256 {
+        let pattern = pattern as u8;
+        let matching = HQ2X_RULES.iter().filter(|rule| rule.1.iter().any(|&(mask, value)| (pattern & mask) == value));
+        let mut case = Hq2xCase { edge: None, sharp: Hq2xBlend::Soft, smooth: Hq2xBlend::Soft };
+        for &(edge, _, blend) in matching {
+            if edge.is_none() {
+                case.smooth = blend;
+                break;
+            }
+            if case.edge.is_none() {
+                case.edge = edge;
+                case.sharp = blend;
+            }
+        }
+        table.push(case);
+    }
+    return table;
+}
+
+fn hq2x_pixel(w: &[u32; 9], mix: Hq2xBlend) -> u32 {
+    let (a, b, d, e) = (w[0], w[HQ2X_B], w[HQ2X_D], w[4]);
+    match mix {
+        Hq2xBlend::Centre => return e,
+        Hq2xBlend::Corner => return blend(e, 3, a, 1, e, 0, 2),
+        Hq2xBlend::Left => return blend(e, 3, d, 1, e, 0, 2),
+        Hq2xBlend::Up => return blend(e, 3, b, 1, e, 0, 2),
+        Hq2xBlend::Edges => return blend(e, 2, d, 1, b, 1, 2),
+        Hq2xBlend::CornerUp => return blend(e, 2, a, 1, b, 1, 2),
+        Hq2xBlend::CornerLeft => return blend(e, 2, a, 1, d, 1, 2),
+        Hq2xBlend::MostlyUp => return blend(e, 5, b, 2, d, 1, 3),
+        Hq2xBlend::MostlyLeft => return blend(e, 5, d, 2, b, 1, 3),
+        Hq2xBlend::Soft => return blend(e, 6, d, 1, b, 1, 3),
+        Hq2xBlend::Softer => return blend(e, 14, d, 1, b, 1, 4),
+        Hq2xBlend::Strong => return blend(e, 2, d, 3, b, 3, 3),
+    }
+}
+
+fn hq2x_block(table: &[Hq2xCase], n: &[[u32; 5]; 5], out: &mut [u32]) {
+    for corner in 0..4 {
+        let mut w = [0; 9];
+        for i in 0..9 {
+            let position = HQ2X_VIEWS[corner][i];
+            w[i] = n[1 + position / 3][1 + position % 3];
+        }
+        let mut pattern = 0;
+        for (bit, &i) in [0, 1, 2, 3, 5, 6, 7, 8].iter().enumerate() {
+            if is_different(w[i], w[4]) {
+                pattern = pattern | (1 << bit);
+            }
+        }
+        let case = table[pattern];
+        let blend = match case.edge {
+            Some((first, second)) if is_different(w[first], w[second]) => case.sharp,
+            _ => case.smooth,
+        };
+        out[corner] = hq2x_pixel(&w, blend);
+    }
+}
+
+fn xbr_difference(a: u32, b: u32) -> i32 {
+    let (ya, ua, va) = yuv(a);
+    let (yb, ub, vb) = yuv(b);
+    return (ya - yb).abs() + (ua - ub).abs() + (va - vb).abs();
+}
+
+fn xbr_equal(a: u32, b: u32) -> bool {
+    return xbr_difference(a, b) < 155;
+}
+
+// moves a colour the given eighths of the way towards another
+fn towards(from: u32, to: u32, eighths: i32) -> u32 {
+    let mut out = 0;
+    for shift in [0, 8, 16].iter() {
+        let a = ((from >> shift) & 0xFF) as i32;
+        let b = ((to >> shift) & 0xFF) as i32;
+        out = out | (((a + (((b - a) * eighths) >> 3)) as u32) << shift);
+    }
+    return out;
+}
+
+// one pass of 2xBR for the bottom right corner of the block, with the neighbours named
+//       B  C
+//    D  E  F  F4
+//    G  H  I  I4
+//       H5 I5
+// when the edge along the E-I diagonal is weaker than the one across it, the
+// corner takes on the closer of F and H, and shallow edges also blend into the
+// pixel to its left or above it
+fn xbr_pass(n: &[[u32; 5]; 5], out: &mut [u32], corner: usize, left: usize, up: usize) {
+    let (b, c) = (n[1][2], n[1][3]);
+    let (d, e, f, f4) = (n[2][1], n[2][2], n[2][3], n[2][4]);
+    let (g, h, i, i4) = (n[3][1], n[3][2], n[3][3], n[3][4]);
+    let (h5, i5) = (n[4][2], n[4][3]);
+    if e == h || e == f {
+        return;
+    }
+    let across = xbr_difference(e, c) + xbr_difference(e, g) + xbr_difference(i, h5) + xbr_difference(i, f4) + 4 * xbr_difference(h, f);
+    let along = xbr_difference(h, d) + xbr_difference(h, i5) + xbr_difference(f, i4) + xbr_difference(f, b) + 4 * xbr_difference(e, i);
+    if across > along {
+        return;
+    }
+    let closer = if xbr_difference(e, f) <= xbr_difference(e, h) { f } else { h };
+    let edge = (!xbr_equal(f, b) && !xbr_equal(h, d))
+        || (xbr_equal(e, i) && !xbr_equal(f, i4) && !xbr_equal(h, i5))
+        || xbr_equal(e, g)
+        || xbr_equal(e, c);
+    if across == along || !edge {
+        out[corner] = towards(out[corner], closer, 4);
+        return;
+    }
+    let slope_left = xbr_difference(f, g);
+    let slope_up = xbr_difference(h, c);
+    let reaches_left = 2 * slope_left <= slope_up && e != g && d != g;
+    let reaches_up = slope_left >= 2 * slope_up && e != c && b != c;
+    if reaches_left && reaches_up {
+        out[corner] = towards(out[corner], closer, 7);
+        out[left] = towards(out[left], closer, 2);
+        out[up] = out[left];
+    } else if reaches_left {
+        out[corner] = towards(out[corner], closer, 6);
+        out[left] = towards(out[left], closer, 2);
+    } else if reaches_up {
+        out[corner] = towards(out[corner], closer, 6);
+        out[up] = towards(out[up], closer, 2);
+    } else {
+        out[corner] = towards(out[corner], closer, 4);
+    }
+}
+
+fn xbr2x_block(n: &[[u32; 5]; 5], out: &mut [u32]) {
+    for pixel in out.iter_mut() {
+        *pixel = n[2][2];
+    }
+    // the passes build on each other: the bottom right corner, then the top right,
+    // top left and bottom left ones turned into the bottom right
+    let n90 = rotate(n);
+    let n180 = rotate(&n90);
+    let n270 = rotate(&n180);
+    xbr_pass(n, out, 3, 2, 1);
+    xbr_pass(&n270, out, 1, 3, 0);
+    xbr_pass(&n180, out, 0, 1, 2);
+    xbr_pass(&n90, out, 2, 0, 3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K: u32 = 0x000000;
+    const W: u32 = 0xFFFFFF;
+
+    fn scale_pixels(scaler: Scaler, pixels: &[u32], width: usize) -> Vec<u32> {
+        let height = pixels.len() / width;
+        return to_pixels(&apply(scaler, &to_rgba(pixels), width, height));
+    }
+
+    // the output block of the input pixel at x, y
+    fn block(out: &[u32], width: usize, scale: usize, x: usize, y: usize) -> Vec<u32> {
+        let mut pixels = Vec::new();
+        for by in 0..scale {
+            for bx in 0..scale {
+                pixels.push(out[(y * scale + by) * width * scale + x * scale + bx]);
+            }
+        }
+        return pixels;
+    }
+
+    // a white area with its top left corner cut off by a black diagonal
+    const DIAGONAL: [u32; 9] = [
+        K, K, W,
+        K, W, W,
+        W, W, W,
+    ];
+
+    #[test]
+    fn scale2x_fills_in_the_diagonal() {
+        let out = scale_pixels(Scaler::Scale2x, &DIAGONAL, 3);
+        assert_eq!(block(&out, 3, 2, 1, 1), vec![K, W, W, W]);
+        assert_eq!(block(&out, 3, 2, 0, 0), vec![K, K, K, K]);
+        assert_eq!(block(&out, 3, 2, 2, 2), vec![W, W, W, W]);
+    }
+
+    #[test]
+    fn scale3x_extends_the_edge_where_the_corner_differs() {
+        let out = scale_pixels(Scaler::Scale3x, &DIAGONAL, 3);
+        assert_eq!(block(&out, 3, 3, 1, 1), vec![K, W, W, W, W, W, W, W, W]);
+        let mut pixels = DIAGONAL;
+        pixels[2] = K;
+        let out = scale_pixels(Scaler::Scale3x, &pixels, 3);
+        assert_eq!(block(&out, 3, 3, 1, 1), vec![K, K, W, W, W, W, W, W, W]);
+    }
+
+    #[test]
+    fn hq2x_blends_the_corner_of_the_diagonal() {
+        let out = scale_pixels(Scaler::Hq2x, &DIAGONAL, 3);
+        assert_eq!(block(&out, 3, 2, 1, 1), vec![0x7F7F7F, W, W, W]);
+        // a lone pixel keeps 14/16 of its colour in every corner
+        let mut pixels = [K; 9];
+        pixels[4] = W;
+        let out = scale_pixels(Scaler::Hq2x, &pixels, 3);
+        assert_eq!(block(&out, 3, 2, 1, 1), vec![0xDFDFDF; 4]);
+    }
+
+    #[test]
+    fn hq2x_keeps_the_corner_between_two_different_edges() {
+        // red to the left and blue above meet at the corner without a shared edge
+        let mut pixels = DIAGONAL;
+        pixels[1] = 0x0000FF;
+        pixels[3] = 0xFF0000;
+        let out = scale_pixels(Scaler::Hq2x, &pixels, 3);
+        assert_eq!(block(&out, 3, 2, 1, 1)[0], W);
+    }
+
+    #[test]
+    fn hq2x_colours_within_the_thresholds_count_as_the_same() {
+        assert!(!is_different(0x808080, 0x8A8A8A));
+        assert!(is_different(0x808080, 0x8080A0));
+        let mut pixels = [0x808080; 9];
+        pixels[0] = 0x8A8A8A;
+        let out = scale_pixels(Scaler::Hq2x, &pixels, 3);
+        assert_eq!(block(&out, 3, 2, 1, 1), vec![0x808080; 4]);
+    }
+
+    #[test]
+    fn hq2x_cases_test_at_most_one_edge() {
+        let table = new_hq2x_table();
+        assert_eq!(table.len(), 256);
+        for pattern in 0..256 {
+            let edges: Vec<_> = HQ2X_RULES.iter()
+                .filter(|rule| rule.0.is_some() && rule.1.iter().any(|&(mask, value)| ((pattern as u8) & mask) == value))
+                .map(|rule| rule.0)
+                .collect();
+            assert!(edges.windows(2).all(|pair| pair[0] == pair[1]), "pattern {:02X}", pattern);
+        }
+        // nothing differs: E softened with its edge neighbours
+        assert_eq!(table[0], Hq2xCase { edge: None, sharp: Hq2xBlend::Soft, smooth: Hq2xBlend::Edges });
+        // everything differs: E, or mostly E where the edges match
+        assert_eq!(table[255], Hq2xCase { edge: Some((HQ2X_D, HQ2X_B)), sharp: Hq2xBlend::Centre, smooth: Hq2xBlend::Softer });
+    }
+
+    #[test]
+    fn xbr_smooths_the_diagonal_and_keeps_flat_areas() {
+        let mut pixels = vec![W; 8 * 8];
+        for y in 0..8 {
+            for x in 0..8 {
+                if x + y < 8 {
+                    pixels[y * 8 + x] = K;
+                }
+            }
+        }
+        let out = scale_pixels(Scaler::Xbr2x, &pixels, 8);
+        // the first white pixel of a row blends its corner towards the black side
+        let edge = block(&out, 8, 2, 4, 4);
+        assert!(edge[0] != K && edge[0] != W);
+        assert_eq!(edge[3], W);
+        assert_eq!(block(&out, 8, 2, 7, 7), vec![W; 4]);
+        assert_eq!(block(&out, 8, 2, 0, 0), vec![K; 4]);
+    }
+}
@@ -0,0 +1,57 @@
+// Plays the samples produced by the APU out of a ring buffer and reports the
+// buffer fill level back to the emulator so it can adjust its output rate.
+const BUFFER_SIZE = 8192;
+const REPORT_INTERVAL = 8;
+
+class NesAudioProcessor extends AudioWorkletProcessor {
+  constructor() {
+    super();
+    this.buffer = new Float32Array(BUFFER_SIZE);
+    this.readIndex = 0;
+    this.writeIndex = 0;
+    this.count = 0;
+    this.lastSample = 0;
+    this.quantum = 0;
+    this.port.onmessage = (event) => this.push(event.data);
+  }
+
+  push(samples) {
+    // null flushes whatever was queued while the context was suspended
+    if (samples === null) {
+      this.readIndex = 0;
+      this.writeIndex = 0;
+      this.count = 0;
+      return;
+    }
+    for (let i = 0; i < samples.length; i++) {
+      if (this.count >= BUFFER_SIZE) {
+        break;
+      }
+      this.buffer[this.writeIndex] = samples[i];
+      this.writeIndex = (this.writeIndex + 1) % BUFFER_SIZE;
+      this.count++;
+    }
+  }
+
+  process(inputs, outputs) {
+    const output = outputs[0][0];
+    for (let i = 0; i < output.length; i++) {
+      if (this.count > 0) {
+        this.lastSample = this.buffer[this.readIndex];
+        this.readIndex = (this.readIndex + 1) % BUFFER_SIZE;
+        this.count--;
+      }
+      // on underrun hold the last sample instead of dropping to zero
+      output[i] = this.lastSample;
+    }
+
+    this.quantum++;
+    if (this.quantum >= REPORT_INTERVAL) {
+      this.quantum = 0;
+      this.port.postMessage(this.count);
+    }
+    return true;
+  }
+}
+
+registerProcessor("nes-audio-processor", NesAudioProcessor);
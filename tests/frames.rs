@@ -0,0 +1,36 @@
+// runs the golden frame manifests through the headless runner
+use std::process::Command;
+
+fn check_manifest(manifest: &str) {
+    let output = Command::new(env!("CARGO_BIN_EXE_headless"))
+        .arg("--manifest")
+        .arg(manifest)
+        .output()
+        .expect("couldn't run the headless runner");
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{} failed:\n{}{}", manifest, report, String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn golden_frames_match() {
+    check_manifest(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/frames/manifest.txt"));
+}
+
+// the blargg suites are not in the repository, see the manifests for where they go
+#[test]
+#[ignore]
+fn sprite_hit_tests_pass() {
+    check_manifest(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/blargg/sprite_hit_tests.txt"));
+}
+
+#[test]
+#[ignore]
+fn ppu_vbl_nmi_pass() {
+    check_manifest(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/blargg/ppu_vbl_nmi.txt"));
+}
+
+#[test]
+#[ignore]
+fn apu_test_pass() {
+    check_manifest(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/blargg/apu_test.txt"));
+}
@@ -0,0 +1,50 @@
+; shared start of the test roms: waits for the ppu to warm up, clears the
+; ram, hides every sprite of the oam buffer at $0200 and loads the 32 colours
+; at the palette label of the rom
+
+reset:
+    sei
+    cld
+    ldx #$40
+    stx $4017           ; no frame irq
+    ldx #$FF
+    txs
+    inx
+    stx $2000
+    stx $2001
+    stx $4010
+    bit $2002
+vblank1:
+    bit $2002
+    bpl vblank1
+
+    txa
+clear_ram:
+    sta $00,x
+    sta $0100,x
+    sta $0300,x
+    sta $0400,x
+    sta $0500,x
+    sta $0600,x
+    sta $0700,x
+    lda #$FF
+    sta $0200,x
+    lda #$00
+    inx
+    bne clear_ram
+
+vblank2:
+    bit $2002
+    bpl vblank2
+
+    lda #$3F
+    sta $2006
+    lda #$00
+    sta $2006
+    ldx #$00
+load_palette:
+    lda palette,x
+    sta $2007
+    inx
+    cpx #32
+    bne load_palette
@@ -0,0 +1,131 @@
+; shows the buttons held on both controllers as a row of tiles each,
+; A B Select Start Up Down Left Right from the left with a solid tile for a
+; held button, and moves a sprite with the d-pad of controller 1
+
+pad1 = $00
+pad2 = $01
+tmp = $02
+
+    .db "NES", $1A, 1, 1, $00, $00  ; nrom, 16k prg, 8k chr, horizontal mirroring
+    .dsb 8, $00
+    .base $8000
+
+    .include "init.asm"
+
+    lda #120
+    sta $0200
+    lda #8
+    sta $0201
+    lda #$00
+    sta $0202
+    lda #124
+    sta $0203
+
+    lda #0
+    sta $2005
+    sta $2005
+    lda #$80
+    sta $2000
+    lda #$1E
+    sta $2001
+forever:
+    jmp forever
+
+nmi:
+    pha
+    txa
+    pha
+    lda #$00
+    sta $2003
+    lda #$02
+    sta $4014
+
+    lda #$20
+    sta $2006
+    lda #$84
+    sta $2006
+    lda pad1
+    sta tmp
+    ldx #8
+show1:
+    asl tmp
+    lda #6
+    bcc released1
+    lda #1
+released1:
+    sta $2007
+    dex
+    bne show1
+
+    lda #$20
+    sta $2006
+    lda #$C4
+    sta $2006
+    lda pad2
+    sta tmp
+    ldx #8
+show2:
+    asl tmp
+    lda #6
+    bcc released2
+    lda #1
+released2:
+    sta $2007
+    dex
+    bne show2
+
+    bit $2002
+    lda #0
+    sta $2005
+    sta $2005
+
+    ; the first bit read ends up in bit 7, A
+    lda #1
+    sta $4016
+    lda #0
+    sta $4016
+    ldx #8
+read:
+    lda $4016
+    lsr a
+    rol pad1
+    lda $4017
+    lsr a
+    rol pad2
+    dex
+    bne read
+
+    lda pad1
+    and #$01
+    beq not_right
+    inc $0203
+not_right:
+    lda pad1
+    and #$02
+    beq not_left
+    dec $0203
+not_left:
+    lda pad1
+    and #$04
+    beq not_down
+    inc $0200
+not_down:
+    lda pad1
+    and #$08
+    beq not_up
+    dec $0200
+not_up:
+    pla
+    tax
+    pla
+irq:
+    rti
+
+palette:
+    .db $0F,$00,$10,$30, $0F,$00,$10,$30, $0F,$00,$10,$30, $0F,$00,$10,$30
+    .db $0F,$16,$27,$30, $0F,$16,$27,$30, $0F,$16,$27,$30, $0F,$16,$27,$30
+
+    .pad $BFFA
+    .dw nmi, reset, irq
+
+    .include "tiles.asm"
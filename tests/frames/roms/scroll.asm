@@ -0,0 +1,115 @@
+; two nametables of tiles and attributes scrolled diagonally, one pixel
+; right every frame and one down every other frame, wrapping through the
+; second nametable and the 240 line height
+
+frame = $00
+frame_hi = $01
+scroll_y = $02
+tmp = $03
+
+    .db "NES", $1A, 1, 1, $01, $00  ; nrom, 16k prg, 8k chr, vertical mirroring
+    .dsb 8, $00
+    .base $8000
+
+    .include "init.asm"
+
+    ; nametable 0, tile (x + y) & 7 with attributes counting up
+    lda #$20
+    sta $2006
+    lda #$00
+    sta $2006
+    ldy #0
+row0:
+    ldx #0
+column0:
+    stx tmp
+    tya
+    clc
+    adc tmp
+    and #7
+    sta $2007
+    inx
+    cpx #32
+    bne column0
+    iny
+    cpy #30
+    bne row0
+    ldx #0
+attributes0:
+    stx $2007
+    inx
+    cpx #64
+    bne attributes0
+
+    ; nametable 1, tile (x ^ y) & 7 with attributes counting down
+    ldy #0
+row1:
+    ldx #0
+column1:
+    stx tmp
+    tya
+    eor tmp
+    and #7
+    sta $2007
+    inx
+    cpx #32
+    bne column1
+    iny
+    cpy #30
+    bne row1
+    ldx #0
+attributes1:
+    txa
+    eor #$FF
+    sta $2007
+    inx
+    cpx #64
+    bne attributes1
+
+    lda #0
+    sta $2005
+    sta $2005
+    lda #$80
+    sta $2000
+    lda #$1E
+    sta $2001
+forever:
+    jmp forever
+
+nmi:
+    pha
+    inc frame
+    bne same_page
+    inc frame_hi
+same_page:
+    lda frame
+    and #1
+    bne scroll
+    inc scroll_y
+    lda scroll_y
+    cmp #240
+    bne scroll
+    lda #0
+    sta scroll_y
+scroll:
+    bit $2002
+    lda frame
+    sta $2005
+    lda scroll_y
+    sta $2005
+    lda frame_hi
+    and #1
+    ora #$80
+    sta $2000
+    pla
+irq:
+    rti
+
+palette:
+    .db $0F,$16,$2A,$12, $0F,$27,$1A,$30, $0F,$14,$38,$21, $0F,$00,$10,$3D
+    .db $0F,$16,$2A,$12, $0F,$27,$1A,$30, $0F,$14,$38,$21, $0F,$00,$10,$3D
+
+    .pad $BFFA
+    .dw nmi, reset, irq
+
+    .include "tiles.asm"
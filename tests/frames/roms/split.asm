@@ -0,0 +1,159 @@
+; a status bar split with sprite 0: the bar stays still while the playfield
+; below the hit on line 24 scrolls one pixel right every frame. the same
+; write after the hit switches the emphasis bits every 32 frames and
+; greyscale every 16 for the rest of the frame
+
+frame = $00
+frame_hi = $01
+tmp = $02
+
+    .db "NES", $1A, 1, 1, $01, $00  ; nrom, 16k prg, 8k chr, vertical mirroring
+    .dsb 8, $00
+    .base $8000
+
+    .include "init.asm"
+
+    ; nametable 0, bands in the top 3 rows, solid tile 3 under sprite 0
+    ; on row 3, then tile (x + y) & 7
+    lda #$20
+    sta $2006
+    lda #$00
+    sta $2006
+    ldx #96
+bar:
+    lda #7
+    sta $2007
+    dex
+    bne bar
+    ldy #3
+row0:
+    ldx #0
+column0:
+    stx tmp
+    tya
+    clc
+    adc tmp
+    and #7
+    sta $2007
+    inx
+    cpx #32
+    bne column0
+    iny
+    cpy #30
+    bne row0
+    ldx #64
+attributes0:
+    lda #$E4
+    sta $2007
+    dex
+    bne attributes0
+    lda #$20
+    sta $2006
+    lda #$70
+    sta $2006
+    lda #3
+    sta $2007
+
+    ; nametable 1, tile (x ^ y) & 7
+    lda #$24
+    sta $2006
+    lda #$00
+    sta $2006
+    ldy #0
+row1:
+    ldx #0
+column1:
+    stx tmp
+    tya
+    eor tmp
+    and #7
+    sta $2007
+    inx
+    cpx #32
+    bne column1
+    iny
+    cpy #30
+    bne row1
+    ldx #64
+attributes1:
+    lda #$1B
+    sta $2007
+    dex
+    bne attributes1
+
+    ; sprite 0, solid colour 3 over the solid tile
+    lda #23
+    sta $0200
+    lda #3
+    sta $0201
+    lda #$00
+    sta $0202
+    lda #128
+    sta $0203
+
+    lda #0
+    sta $2005
+    sta $2005
+    lda #$80
+    sta $2000
+    lda #$1E
+    sta $2001
+
+main:
+    bit $2002
+    bvs main            ; the hit flag of the last frame clears on the pre-render line
+wait_hit:
+    bit $2002
+    bvc wait_hit
+    lda frame
+    sta $2005
+    lda #0
+    sta $2005
+    lda frame_hi
+    and #1
+    ora #$80
+    sta $2000
+    lda frame
+    and #$10
+    lsr a
+    lsr a
+    lsr a
+    lsr a
+    sta tmp
+    lda frame
+    and #$E0
+    ora #$1E
+    ora tmp
+    sta $2001
+    jmp main
+
+nmi:
+    pha
+    lda #$00
+    sta $2003
+    lda #$02
+    sta $4014
+    inc frame
+    bne same_page
+    inc frame_hi
+same_page:
+    bit $2002
+    lda #0
+    sta $2005
+    sta $2005
+    lda #$80
+    sta $2000
+    lda #$1E
+    sta $2001
+    pla
+irq:
+    rti
+
+palette:
+    .db $0F,$16,$2A,$12, $0F,$27,$1A,$30, $0F,$14,$38,$21, $0F,$00,$10,$3D
+    .db $0F,$30,$30,$30, $0F,$30,$30,$30, $0F,$30,$30,$30, $0F,$30,$30,$30
+
+    .pad $BFFA
+    .dw nmi, reset, irq
+
+    .include "tiles.asm"
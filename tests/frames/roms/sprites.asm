@@ -0,0 +1,106 @@
+; 8x16 sprites over a background of diagonal lines on the left half:
+;  y 40   ten sprites on one line, the ninth and tenth fall to the sprite limit
+;  y 80   no flip, horizontal, vertical and both flips
+;  y 120  behind the background, in front, and a back sprite above a front one
+;  y 160  at x 0 and 4, cut by the left column clipping
+;  y 200  two sprites at the same spot, the lower oam index wins
+; the sprites of the first two lines move one pixel right every frame
+
+tmp = $00
+
+    .db "NES", $1A, 1, 1, $00, $00  ; nrom, 16k prg, 8k chr, horizontal mirroring
+    .dsb 8, $00
+    .base $8000
+
+    .include "init.asm"
+
+    lda #$20
+    sta $2006
+    lda #$00
+    sta $2006
+    ldy #30
+rows:
+    ldx #16
+left_half:
+    lda #5
+    sta $2007
+    dex
+    bne left_half
+    ldx #16
+right_half:
+    lda #0
+    sta $2007
+    dex
+    bne right_half
+    dey
+    bne rows
+    ldx #64
+attributes:
+    lda #$00
+    sta $2007
+    dex
+    bne attributes
+
+    ldx #0
+copy_sprites:
+    lda sprites,x
+    sta $0200,x
+    inx
+    cpx #sprites_end-sprites
+    bne copy_sprites
+
+    lda #0
+    sta $2005
+    sta $2005
+    lda #$A0            ; nmi, 8x16 sprites
+    sta $2000
+    lda #$1A            ; sprites hidden in the left 8 columns
+    sta $2001
+forever:
+    jmp forever
+
+nmi:
+    pha
+    txa
+    pha
+    lda #$00
+    sta $2003
+    lda #$02
+    sta $4014
+    ldx #0
+move:
+    inc $0203,x
+    inx
+    inx
+    inx
+    inx
+    cpx #14*4
+    bne move
+    bit $2002
+    lda #0
+    sta $2005
+    sta $2005
+    pla
+    tax
+    pla
+irq:
+    rti
+
+palette:
+    .db $0F,$00,$10,$21, $0F,$00,$10,$21, $0F,$00,$10,$21, $0F,$00,$10,$21
+    .db $0F,$16,$27,$30, $0F,$1A,$2A,$30, $0F,$12,$22,$30, $0F,$14,$24,$30
+
+sprites:
+    ; y - 1, tile, attributes, x
+    .db 39,8,$00,20, 39,8,$01,44, 39,8,$02,68, 39,8,$03,92, 39,8,$00,116
+    .db 39,8,$01,140, 39,8,$02,164, 39,8,$03,188, 39,8,$00,212, 39,8,$01,236
+    .db 79,8,$01,40, 79,8,$41,64, 79,8,$81,88, 79,8,$C1,112
+    .db 119,8,$22,40, 119,8,$02,72, 119,8,$23,100, 119,8,$00,104
+    .db 159,8,$03,0, 159,8,$03,4
+    .db 199,8,$00,160, 199,8,$02,164
+sprites_end:
+
+    .pad $BFFA
+    .dw nmi, reset, irq
+
+    .include "tiles.asm"
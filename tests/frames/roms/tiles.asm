@@ -0,0 +1,17 @@
+; pattern table 0, shared by the test roms
+;  0 blank            1-3 solid colour 1, 2 and 3
+;  4 colour 1/2 checkerboard   5 colour 3 diagonal on colour 0
+;  6 colour 1 box around colour 2   7 bands of colour 0, 1, 2 and 3
+;  8/9 an asymmetric shape, the top and bottom of the 8x16 sprite
+    .base $0000
+    .dsb 16, $00
+    .db $FF,$FF,$FF,$FF,$FF,$FF,$FF,$FF, $00,$00,$00,$00,$00,$00,$00,$00
+    .db $00,$00,$00,$00,$00,$00,$00,$00, $FF,$FF,$FF,$FF,$FF,$FF,$FF,$FF
+    .db $FF,$FF,$FF,$FF,$FF,$FF,$FF,$FF, $FF,$FF,$FF,$FF,$FF,$FF,$FF,$FF
+    .db $CC,$CC,$33,$33,$CC,$CC,$33,$33, $33,$33,$CC,$CC,$33,$33,$CC,$CC
+    .db $80,$40,$20,$10,$08,$04,$02,$01, $80,$40,$20,$10,$08,$04,$02,$01
+    .db $FF,$81,$81,$81,$81,$81,$81,$FF, $00,$00,$3C,$3C,$3C,$3C,$00,$00
+    .db $00,$00,$FF,$FF,$00,$00,$FF,$FF, $00,$00,$00,$00,$FF,$FF,$FF,$FF
+    .db $FE,$80,$80,$FC,$80,$80,$80,$00, $00,$7E,$40,$40,$40,$7E,$00,$00
+    .db $F0,$88,$84,$82,$81,$82,$84,$F8, $0F,$07,$03,$01,$00,$01,$03,$07
+    .pad $2000
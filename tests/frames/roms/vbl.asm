@@ -0,0 +1,116 @@
+; reports its result at $6000 the way blargg's test roms do, so the pass
+; check of the manifest has a rom to run: reading $2002 clears the vblank
+; flag, the nmi fires once a frame while it is enabled and not at all while
+; it isn't
+
+nmis = $00
+text = $02              ; pointer to the text of the result
+
+status = $6000
+
+    .db "NES", $1A, 1, 1, $00, $00  ; nrom, 16k prg, 8k chr
+    .dsb 8, $00
+    .base $8000
+
+    .include "init.asm"
+
+    lda #$80            ; running
+    sta status
+    lda #$DE
+    sta status + 1
+    lda #$B0
+    sta status + 2
+    lda #$61
+    sta status + 3
+
+    ; the read that sees the flag clears it
+wait_vblank:
+    bit $2002
+    bpl wait_vblank
+    bit $2002
+    bpl cleared
+    lda #2
+    ldx #<not_cleared
+    ldy #>not_cleared
+    jmp report
+cleared:
+
+    ; an nmi a frame while it is enabled
+    lda #$80
+    sta $2000
+    ldx #0
+    ldy #0
+wait_nmi:
+    lda nmis
+    bne nmi_seen
+    inx
+    bne wait_nmi
+    iny
+    cpy #20             ; more than a frame of the loop
+    bne wait_nmi
+    lda #3
+    ldx #<no_nmi
+    ldy #>no_nmi
+    jmp report
+nmi_seen:
+
+    ; none while it is disabled
+    lda #$00
+    sta $2000
+    sta nmis
+    ldx #3
+wait_frames:
+    bit $2002
+    bpl wait_frames
+    dex
+    bne wait_frames
+    lda nmis
+    beq passed
+    lda #4
+    ldx #<disabled_nmi
+    ldy #>disabled_nmi
+    jmp report
+passed:
+    lda #0
+    ldx #<passed_text
+    ldy #>passed_text
+
+    ; a = the result, x and y = the text
+report:
+    stx text
+    sty text + 1
+    pha
+    ldy #0
+copy_text:
+    lda (text),y
+    sta status + 4,y
+    beq copied
+    iny
+    bne copy_text
+copied:
+    pla
+    sta status
+done:
+    jmp done
+
+nmi:
+    inc nmis
+irq:
+    rti
+
+passed_text:
+    .db "Passed", 0
+not_cleared:
+    .db "Reading $2002 didn't clear the vblank flag", 0
+no_nmi:
+    .db "No nmi with it enabled", 0
+disabled_nmi:
+    .db "Nmi with it disabled", 0
+
+palette:
+    .dsb 32, $0F
+
+    .pad $BFFA
+    .dw nmi, reset, irq
+
+    .include "tiles.asm"